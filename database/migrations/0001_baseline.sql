@@ -0,0 +1,62 @@
+-- The schema from before migrations existed. Databases created from it are recorded as being at this
+-- version the first time the bot migrates them.
+
+CREATE TABLE IF NOT EXISTS Players (
+       discordName TEXT PRIMARY KEY,
+       minecraftName TEXT UNIQUE,
+       registrationCode TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS AuthenticationRequests(
+       id SERIAL PRIMARY KEY,
+       minecraftName TEXT NOT NULL,
+       minecraftServer TEXT NOT NULL,
+       ipAddress TEXT NOT NULL,
+       handled BOOLEAN NOT NULL DEFAULT FALSE,
+       created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (minecraftName) REFERENCES Players(minecraftName) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS PlayerAuthentications (
+       id SERIAL,
+       authRequestId INT,
+       discordName TEXT NOT NULL UNIQUE,
+       expiration TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP + (30 * INTERVAL '1 minute'),
+       PRIMARY KEY (id),
+       FOREIGN KEY (discordName) REFERENCES Players(discordName) ON DELETE CASCADE ON UPDATE CASCADE,
+       FOREIGN KEY (authRequestId) REFERENCES AuthenticationRequests(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE OR REPLACE VIEW AuthenticatedPlayers AS
+       SELECT id, authRequestId, discordName, minecraftName
+       FROM Players
+       INNER JOIN PLayerAuthentications USING (discordName)
+       WHERE expiration >= now()::timestamp;
+
+CREATE OR REPLACE FUNCTION NotifyBot()
+    RETURNS trigger AS $$
+    BEGIN
+        PERFORM pg_notify(CAST('bot_updates' AS TEXT), CAST(NEW.id AS TEXT));
+        
+        DELETE 
+        FROM AuthenticationRequests
+        WHERE handled = TRUE and (created + (30 * INTERVAL '1 minute')) > now()::timestamp;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION NotifyMinecraft()
+    RETURNS trigger AS $$
+    BEGIN
+        PERFORM pg_notify(CAST('approved_auths' AS TEXT), CAST(NEW.id AS TEXT));
+        RETURN OLD;
+    END;
+    $$ LANGUAGE plpgsql;
+
+CREATE TRIGGER InsertAuthRequests
+    AFTER INSERT ON AuthenticationRequests
+    FOR EACH ROW EXECUTE PROCEDURE NotifyBot();
+
+CREATE TRIGGER UpdateAuthRequests
+    AFTER INSERT ON PlayerAuthentications
+    FOR EACH ROW EXECUTE PROCEDURE NotifyMinecraft();
@@ -0,0 +1,29 @@
+-- Registration codes expire and only their hash is stored
+ALTER TABLE Players
+       ADD COLUMN registrationCodeCreated TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       ADD COLUMN registrationCodeExpiration TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- Only the hash of a registration code is stored, the code itself is shown to the Discord user once
+CREATE OR REPLACE FUNCTION HashRegistrationCode(code TEXT)
+    RETURNS TEXT AS $$
+        SELECT encode(sha256(convert_to(code, 'UTF8')), 'hex');
+    $$ LANGUAGE sql IMMUTABLE;
+
+-- Codes handed out before they could expire are expired right away, pending players can get a new one
+-- with /register regenerate
+UPDATE Players SET registrationCode = HashRegistrationCode(registrationCode);
+
+-- Used by the Minecraft server when a player enters /register <code>.
+-- Returns false if the code is unknown, has expired or has already been used.
+-- Short codes are upper case and word codes lower case, so the code may be typed in either case.
+CREATE OR REPLACE FUNCTION LinkMinecraftPlayer(code TEXT, player TEXT)
+    RETURNS BOOLEAN AS $$
+    BEGIN
+        UPDATE Players
+        SET minecraftName = player
+        WHERE registrationCode IN (HashRegistrationCode(code), HashRegistrationCode(upper(code)), HashRegistrationCode(lower(code)))
+        AND minecraftName IS NULL
+        AND registrationCodeExpiration >= now()::timestamp;
+        RETURN FOUND;
+    END;
+    $$ LANGUAGE plpgsql;
@@ -0,0 +1,67 @@
+-- Login requests keep their outcome and the history of their states
+ALTER TABLE AuthenticationRequests
+       ADD COLUMN state TEXT NOT NULL DEFAULT 'pending' CHECK (state IN ('pending', 'prompted', 'approved', 'denied', 'expired', 'failed_delivery', 'throttled')),
+       ADD COLUMN discordMessageId TEXT,
+       ADD COLUMN stateChanged TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+-- The outcome of requests handled before is only known for the ones that started a session
+UPDATE AuthenticationRequests
+SET state = CASE
+        WHEN EXISTS (SELECT 1 FROM PlayerAuthentications WHERE authRequestId = AuthenticationRequests.id) THEN 'approved'
+        ELSE 'expired'
+    END
+WHERE handled = TRUE;
+
+CREATE TABLE IF NOT EXISTS AuthenticationRequestStates(
+       id SERIAL PRIMARY KEY,
+       authRequestId INT NOT NULL,
+       state TEXT NOT NULL,
+       discordMessageId TEXT,
+       created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (authRequestId) REFERENCES AuthenticationRequests(id) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- Handled requests are only removed once they are old and no longer back a session
+CREATE OR REPLACE FUNCTION NotifyBot()
+    RETURNS trigger AS $$
+    BEGIN
+        PERFORM pg_notify(CAST('bot_updates' AS TEXT), CAST(NEW.id AS TEXT));
+        
+        DELETE 
+        FROM AuthenticationRequests
+        WHERE handled = TRUE and (created + (30 * INTERVAL '1 minute')) < now()::timestamp
+        AND NOT EXISTS (
+            SELECT 1
+            FROM PlayerAuthentications
+            WHERE authRequestId = AuthenticationRequests.id AND expiration >= now()::timestamp
+        );
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION RecordAuthRequestState()
+    RETURNS trigger AS $$
+    BEGIN
+        IF TG_OP = 'UPDATE' AND OLD.state = NEW.state THEN
+            RETURN NEW;
+        END IF;
+
+        INSERT INTO AuthenticationRequestStates(authRequestId, state, discordMessageId)
+        VALUES (NEW.id, NEW.state, NEW.discordMessageId);
+
+        IF NEW.state = 'denied' THEN
+            PERFORM pg_notify(CAST('denied_auths' AS TEXT), CAST(NEW.id AS TEXT));
+        ELSIF NEW.state = 'expired' THEN
+            PERFORM pg_notify(CAST('expired_auths' AS TEXT), CAST(NEW.id AS TEXT));
+        ELSIF NEW.state = 'failed_delivery' THEN
+            PERFORM pg_notify(CAST('failed_auths' AS TEXT), CAST(NEW.id AS TEXT));
+        ELSIF NEW.state = 'throttled' THEN
+            PERFORM pg_notify(CAST('throttled_auths' AS TEXT), CAST(NEW.id AS TEXT));
+        END IF;
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+
+CREATE TRIGGER AuthRequestStates
+    AFTER INSERT OR UPDATE OF state ON AuthenticationRequests
+    FOR EACH ROW EXECUTE PROCEDURE RecordAuthRequestState();
@@ -0,0 +1,20 @@
+-- Players can lock their account and trust the networks they log in from
+ALTER TABLE Players
+       ADD COLUMN locked BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS TrustedAddresses (
+       id SERIAL PRIMARY KEY,
+       discordName TEXT NOT NULL,
+       ipAddress TEXT NOT NULL,
+       created TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       expiration TIMESTAMP NOT NULL,
+       UNIQUE (discordName, ipAddress),
+       FOREIGN KEY (discordName) REFERENCES Players(discordName) ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE OR REPLACE VIEW AuthenticatedPlayers AS
+       SELECT PlayerAuthentications.id, authRequestId, discordName, Players.minecraftName, expiration, AuthenticationRequests.ipAddress
+       FROM Players
+       INNER JOIN PLayerAuthentications USING (discordName)
+       LEFT JOIN AuthenticationRequests ON (AuthenticationRequests.id = PlayerAuthentications.authRequestId)
+       WHERE expiration >= now()::timestamp;
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS MinecraftServers (
+       name TEXT PRIMARY KEY,
+       requiredRoleId TEXT,
+       sessionMinutes INT CHECK (sessionMinutes > 0),
+       requireApproval BOOLEAN NOT NULL DEFAULT TRUE,
+       maintenance BOOLEAN NOT NULL DEFAULT FALSE
+);
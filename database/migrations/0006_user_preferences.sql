@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS UserPreferences (
+       discordName TEXT PRIMARY KEY,
+       locale TEXT NOT NULL
+);
@@ -0,0 +1,60 @@
+-- Same checksum as is_valid_short_code in src/services/registration_code.rs: the last character is the
+-- sum of every other character's position in the alphabet times its own position, modulo the alphabet length
+CREATE OR REPLACE FUNCTION IsValidShortCode(code TEXT)
+    RETURNS BOOLEAN AS $$
+    DECLARE
+        alphabet CONSTANT TEXT := '23456789ABCDEFGHJKMNPQRSTUVWXYZ';
+        total INT := 0;
+        value INT;
+    BEGIN
+        IF length(code) < 2 THEN
+            RETURN FALSE;
+        END IF;
+
+        FOR i IN 1..length(code) - 1 LOOP
+            value := strpos(alphabet, substr(code, i, 1)) - 1;
+
+            IF value < 0 THEN
+                RETURN FALSE;
+            END IF;
+
+            total := total + i * value;
+        END LOOP;
+
+        RETURN substr(alphabet, total % length(alphabet) + 1, 1) = substr(code, length(code), 1);
+    END;
+    $$ LANGUAGE plpgsql IMMUTABLE;
+
+-- Used by the Minecraft server when a player enters /register <code>. Returns
+--   linked    when the player has been linked to the Discord user of the code
+--   mistyped  when the code is made of short code characters but its checksum does not match, so the
+--             player can be asked to check for a typo
+--   invalid   when the code is unknown, has expired or has already been used
+-- Short codes are upper case and word codes lower case, so the code may be typed in either case.
+CREATE OR REPLACE FUNCTION RegisterMinecraftPlayer(code TEXT, player TEXT)
+    RETURNS TEXT AS $$
+    BEGIN
+        UPDATE Players
+        SET minecraftName = player
+        WHERE registrationCode IN (HashRegistrationCode(code), HashRegistrationCode(upper(code)), HashRegistrationCode(lower(code)))
+        AND minecraftName IS NULL
+        AND registrationCodeExpiration >= now()::timestamp;
+
+        IF FOUND THEN
+            RETURN 'linked';
+        END IF;
+
+        -- Only checked once the lookup failed, as a long code may consist of short code characters as well
+        IF upper(code) ~ '^[2-9A-HJKMNP-Z]+$' AND NOT IsValidShortCode(upper(code)) THEN
+            RETURN 'mistyped';
+        END IF;
+
+        RETURN 'invalid';
+    END;
+    $$ LANGUAGE plpgsql;
+
+-- Kept for Minecraft servers that only tell the player whether the registration succeeded
+CREATE OR REPLACE FUNCTION LinkMinecraftPlayer(code TEXT, player TEXT)
+    RETURNS BOOLEAN AS $$
+        SELECT RegisterMinecraftPlayer(code, player) = 'linked';
+    $$ LANGUAGE sql;
@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+
+use serenity::model::id::UserId;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::CommandDataOption;
+use thiserror::Error;
+
+use super::command::{CommandOption, OptionKind, SubCommand, SubCommandGroup};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ArgumentError {
+    #[error("Please choose one of the subcommands")]
+    MissingSubcommand,
+    #[error("The subcommand '{0}' does not exist")]
+    UnknownSubcommand(String),
+    #[error("The option '{0}' does not exist")]
+    UnknownOption(String),
+    #[error("The option '{0}' is required")]
+    MissingOption(String),
+    #[error("The option '{0}' has an invalid value")]
+    InvalidOption(String),
+    #[error("The option '{0}' must be one of the listed choices")]
+    InvalidChoice(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    User(UserId),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CommandArguments {
+    group: Option<String>,
+    subcommand: Option<String>,
+    values: HashMap<String, OptionValue>,
+}
+
+impl CommandArguments {
+    pub fn parse(
+        options: &[CommandOption],
+        subcommands: &[SubCommand],
+        groups: &[SubCommandGroup],
+        data: &[CommandDataOption],
+    ) -> Result<CommandArguments, ArgumentError> {
+        match data.first() {
+            Some(option) if option.kind == CommandOptionType::SubCommandGroup => {
+                let group = groups
+                    .iter()
+                    .find(|g| g.name == option.name)
+                    .ok_or_else(|| ArgumentError::UnknownSubcommand(option.name.clone()))?;
+                let mut arguments = parse_subcommand(&group.subcommands, &option.options)?;
+                arguments.group = Some(option.name.clone());
+
+                Ok(arguments)
+            }
+            Some(option) if option.kind == CommandOptionType::SubCommand => {
+                parse_subcommand(subcommands, data)
+            }
+            _ if !subcommands.is_empty() || !groups.is_empty() => {
+                Err(ArgumentError::MissingSubcommand)
+            }
+            _ => Ok(CommandArguments {
+                values: parse_values(options, data)?,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    pub fn subcommand(&self) -> Option<&str> {
+        self.subcommand.as_deref()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OptionValue> {
+        self.values.get(name)
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(OptionValue::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_integer(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(OptionValue::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_boolean(&self, name: &str) -> Option<bool> {
+        match self.values.get(name) {
+            Some(OptionValue::Boolean(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<UserId> {
+        match self.values.get(name) {
+            Some(OptionValue::User(value)) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+// The option the user is typing in when Discord asks for autocomplete suggestions
+pub fn get_focused_option(data: &[CommandDataOption]) -> Option<&CommandDataOption> {
+    data.iter().find_map(|option| {
+        if option.focused {
+            Some(option)
+        } else {
+            get_focused_option(&option.options)
+        }
+    })
+}
+
+fn parse_subcommand(
+    subcommands: &[SubCommand],
+    data: &[CommandDataOption],
+) -> Result<CommandArguments, ArgumentError> {
+    let option = match data.first() {
+        Some(option) if option.kind == CommandOptionType::SubCommand => option,
+        _ => return Err(ArgumentError::MissingSubcommand),
+    };
+    let subcommand = subcommands
+        .iter()
+        .find(|s| s.name == option.name)
+        .ok_or_else(|| ArgumentError::UnknownSubcommand(option.name.clone()))?;
+
+    Ok(CommandArguments {
+        subcommand: Some(option.name.clone()),
+        values: parse_values(&subcommand.options, &option.options)?,
+        ..Default::default()
+    })
+}
+
+fn parse_values(
+    options: &[CommandOption],
+    data: &[CommandDataOption],
+) -> Result<HashMap<String, OptionValue>, ArgumentError> {
+    let mut values = HashMap::new();
+
+    for option in data {
+        let definition = options
+            .iter()
+            .find(|o| o.name == option.name)
+            .ok_or_else(|| ArgumentError::UnknownOption(option.name.clone()))?;
+        let value = option.value.as_ref();
+        let value = match definition.kind {
+            OptionKind::String => value.and_then(|v| v.as_str()).map(|v| OptionValue::String(v.to_string())),
+            OptionKind::Integer => value.and_then(|v| v.as_i64()).map(OptionValue::Integer),
+            OptionKind::Boolean => value.and_then(|v| v.as_bool()).map(OptionValue::Boolean),
+            // Users are sent as their id in a string
+            OptionKind::User => value
+                .and_then(|v| v.as_str())
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(|v| OptionValue::User(UserId(v))),
+        };
+        let value = value.ok_or_else(|| ArgumentError::InvalidOption(option.name.clone()))?;
+
+        if !definition.choices.is_empty() && !definition.choices.iter().any(|c| c.value == value) {
+            return Err(ArgumentError::InvalidChoice(option.name.clone()));
+        }
+
+        values.insert(option.name.clone(), value);
+    }
+
+    if let Some(missing) = options.iter().find(|o| o.required && !values.contains_key(o.name)) {
+        return Err(ArgumentError::MissingOption(missing.name.to_string()));
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::command::OptionChoice;
+    use serde_json::json;
+
+    fn data(value: serde_json::Value) -> Vec<CommandDataOption> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn option(name: &'static str, kind: OptionKind, required: bool) -> CommandOption {
+        CommandOption {
+            name,
+            description: "",
+            kind,
+            required,
+            choices: Vec::new(),
+            autocomplete: false,
+        }
+    }
+
+    fn admin_subcommands() -> Vec<SubCommand> {
+        vec![
+            SubCommand {
+                name: "unlock",
+                description: "",
+                options: vec![option("user", OptionKind::User, true)],
+            },
+            SubCommand {
+                name: "list",
+                description: "",
+                options: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn parses_typed_options() {
+        let options = vec![
+            option("name", OptionKind::String, true),
+            option("days", OptionKind::Integer, false),
+            option("notify", OptionKind::Boolean, false),
+        ];
+        let arguments = CommandArguments::parse(
+            &options,
+            &[],
+            &[],
+            &data(json!([
+                { "name": "name", "type": 3, "value": "Steve" },
+                { "name": "days", "type": 4, "value": 7 },
+                { "name": "notify", "type": 5, "value": true }
+            ])),
+        )
+        .unwrap();
+
+        assert_eq!(arguments.subcommand(), None);
+        assert_eq!(arguments.get_string("name"), Some("Steve"));
+        assert_eq!(arguments.get_integer("days"), Some(7));
+        assert_eq!(arguments.get_boolean("notify"), Some(true));
+        assert_eq!(arguments.get_string("days"), None);
+    }
+
+    #[test]
+    fn optional_options_can_be_left_out() {
+        let options = vec![option("days", OptionKind::Integer, false)];
+        let arguments = CommandArguments::parse(&options, &[], &[], &[]).unwrap();
+
+        assert_eq!(arguments.get("days"), None);
+    }
+
+    #[test]
+    fn rejects_missing_required_option() {
+        let options = vec![option("name", OptionKind::String, true)];
+
+        assert_eq!(
+            CommandArguments::parse(&options, &[], &[], &[]),
+            Err(ArgumentError::MissingOption("name".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_value_of_the_wrong_type() {
+        let options = vec![option("days", OptionKind::Integer, true)];
+
+        assert_eq!(
+            CommandArguments::parse(&options, &[], &[], &data(json!([{ "name": "days", "type": 4, "value": "seven" }]))),
+            Err(ArgumentError::InvalidOption("days".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_option() {
+        assert_eq!(
+            CommandArguments::parse(&[], &[], &[], &data(json!([{ "name": "name", "type": 3, "value": "Steve" }]))),
+            Err(ArgumentError::UnknownOption("name".to_string()))
+        );
+    }
+
+    #[test]
+    fn only_accepts_listed_choices() {
+        let mut locale = option("locale", OptionKind::String, true);
+        locale.choices = vec![
+            OptionChoice { name: "English".to_string(), value: OptionValue::String("en".to_string()) },
+            OptionChoice { name: "Svenska".to_string(), value: OptionValue::String("sv".to_string()) },
+        ];
+        let options = vec![locale];
+
+        let arguments = CommandArguments::parse(&options, &[], &[], &data(json!([{ "name": "locale", "type": 3, "value": "sv" }]))).unwrap();
+        assert_eq!(arguments.get_string("locale"), Some("sv"));
+
+        assert_eq!(
+            CommandArguments::parse(&options, &[], &[], &data(json!([{ "name": "locale", "type": 3, "value": "de" }]))),
+            Err(ArgumentError::InvalidChoice("locale".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_subcommand_with_user_option() {
+        let arguments = CommandArguments::parse(
+            &[],
+            &admin_subcommands(),
+            &[],
+            &data(json!([{
+                "name": "unlock",
+                "type": 1,
+                "options": [{ "name": "user", "type": 6, "value": "1001" }]
+            }])),
+        )
+        .unwrap();
+
+        assert_eq!(arguments.subcommand(), Some("unlock"));
+        assert_eq!(arguments.get_user("user"), Some(UserId(1001)));
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_subcommands() {
+        assert_eq!(
+            CommandArguments::parse(&[], &admin_subcommands(), &[], &[]),
+            Err(ArgumentError::MissingSubcommand)
+        );
+        assert_eq!(
+            CommandArguments::parse(&[], &admin_subcommands(), &[], &data(json!([{ "name": "lookup", "type": 1 }]))),
+            Err(ArgumentError::UnknownSubcommand("lookup".to_string()))
+        );
+    }
+
+    #[test]
+    fn parses_subcommand_group() {
+        let groups = vec![SubCommandGroup {
+            name: "account",
+            description: "",
+            subcommands: admin_subcommands(),
+        }];
+        let arguments = CommandArguments::parse(
+            &[],
+            &[],
+            &groups,
+            &data(json!([{
+                "name": "account",
+                "type": 2,
+                "options": [{ "name": "list", "type": 1, "options": [] }]
+            }])),
+        )
+        .unwrap();
+
+        assert_eq!(arguments.group(), Some("account"));
+        assert_eq!(arguments.subcommand(), Some("list"));
+
+        assert_eq!(
+            CommandArguments::parse(
+                &[],
+                &[],
+                &groups,
+                &data(json!([{
+                    "name": "account",
+                    "type": 2,
+                    "options": [{ "name": "unlock", "type": 1, "options": [] }]
+                }])),
+            ),
+            Err(ArgumentError::MissingOption("user".to_string()))
+        );
+    }
+
+    #[test]
+    fn finds_focused_option_in_subcommand() {
+        let data = data(json!([{
+            "name": "lookup",
+            "type": 1,
+            "options": [
+                { "name": "user", "type": 6, "value": "1001" },
+                { "name": "minecraft-name", "type": 3, "value": "Ste", "focused": true }
+            ]
+        }]));
+        let focused = get_focused_option(&data).unwrap();
+
+        assert_eq!(focused.name, "minecraft-name");
+        assert_eq!(focused.value.as_ref().and_then(|v| v.as_str()), Some("Ste"));
+        assert!(get_focused_option(&data[0].options[..1]).is_none());
+    }
+}
@@ -1,205 +1,744 @@
-use std::{sync::{Arc, Mutex}, time::{Instant, Duration}};
+use std::{collections::HashMap, error::Error, sync::{Arc, Mutex}, time::{Instant, Duration}};
 
-use async_std::task;
+use async_std::{future, task};
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
-use futures::channel::mpsc::Receiver;
-use serenity::{http::CacheHttp, CacheAndHttp, utils::Colour};
-use tokio_postgres::{AsyncMessage, NoTls, Notification};
+use futures::channel::{mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender}, oneshot};
+use futures::{stream, StreamExt};
+use serenity::{http::Http, utils::Colour};
+use serenity::model::{application::component::ButtonStyle, channel::Message, id::{ChannelId, GuildId, RoleId, UserId}, Timestamp};
+use tokio::sync::Semaphore;
+use tokio_postgres::NoTls;
+
+use crate::services::database::{AuthenticationState, Database, DatabaseError};
+use crate::services::embed::{minecraft_avatar_url, ButtonAction, ButtonData, EmbedData, ResponseData};
+use crate::services::localization::{Catalog, MessageParameters};
+use crate::services::settings::{AuthenticationSettings, FeatureSettings};
+
+static CUSTOM_ID_PREFIX: &str = "auth";
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("login.trusted-network", &["minecraft_user", "minecraft_server", "ip_address"]),
+    ("login.throttled", &["minecraft_user", "ip_address", "minecraft_server", "duration"]),
+    ("login.prompt", &["minecraft_user", "minecraft_server", "duration"]),
+    ("login.trust", &["days"]),
+    ("login.approved", &["duration", "trust_notice"]),
+    ("login.trust-notice", &["ip_address", "days"]),
+    ("alert.description", &["discord_id"]),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticationResponse {
+    Approve,
+    Trust,
+    Deny,
+    Lock,
+}
+
+impl AuthenticationResponse {
+    fn name(&self) -> &'static str {
+        match self {
+            AuthenticationResponse::Approve => "approve",
+            AuthenticationResponse::Trust => "trust",
+            AuthenticationResponse::Deny => "deny",
+            AuthenticationResponse::Lock => "lock",
+        }
+    }
+
+    pub fn custom_id(&self, request_id: i32) -> String {
+        format!("{}:{}:{}", CUSTOM_ID_PREFIX, self.name(), request_id)
+    }
+
+    pub fn from_custom_id(custom_id: &str) -> Option<(AuthenticationResponse, i32)> {
+        let mut parts = custom_id.split(':');
+
+        if parts.next() != Some(CUSTOM_ID_PREFIX) {
+            return None;
+        }
+
+        let response = match parts.next()? {
+            "approve" => AuthenticationResponse::Approve,
+            "trust" => AuthenticationResponse::Trust,
+            "deny" => AuthenticationResponse::Deny,
+            "lock" => AuthenticationResponse::Lock,
+            _ => return None,
+        };
+        let request_id = parts.next()?.parse::<i32>().ok()?;
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some((response, request_id)),
+        }
+    }
+}
+
+struct PendingAuthentication {
+    discord_user: UserId,
+    tx: oneshot::Sender<AuthenticationResponse>,
+}
+
+/// Authentication requests that are waiting for the Discord user to press one of the buttons in the login DM
+#[derive(Default)]
+pub struct PendingAuthentications {
+    requests: Mutex<HashMap<i32, PendingAuthentication>>,
+}
+
+impl PendingAuthentications {
+    fn add(&self, request_id: i32, discord_user: UserId) -> oneshot::Receiver<AuthenticationResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.requests.lock().unwrap().insert(request_id, PendingAuthentication { discord_user, tx });
+        rx
+    }
+
+    fn remove(&self, request_id: i32) {
+        self.requests.lock().unwrap().remove(&request_id);
+    }
+
+    /// Hands the response of a Discord user over to the task waiting for it.
+    /// Returns false if there is no pending request with the id for that user.
+    pub fn resolve(&self, request_id: i32, discord_user: UserId, response: AuthenticationResponse) -> bool {
+        let mut requests = self.requests.lock().unwrap();
 
-use crate::services::database::Database;
+        match requests.get(&request_id) {
+            Some(pending) if pending.discord_user == discord_user => (),
+            _ => return false,
+        }
+
+        let pending = requests.remove(&request_id).unwrap();
+        pending.tx.send(response).is_ok()
+    }
+
+    pub fn new() -> PendingAuthentications {
+        PendingAuthentications::default()
+    }
+}
+
+fn response_button(response: AuthenticationResponse, request_id: i32, style: ButtonStyle, label: String) -> ButtonData {
+    ButtonData {
+        label,
+        style,
+        action: ButtonAction::CustomId(response.custom_id(request_id)),
+    }
+}
+
+async fn send_response(http: &Http, channel: ChannelId, response: &ResponseData) -> serenity::Result<Message> {
+    channel.send_message(http, |m| m.set_embeds(response.create_embeds()).set_components(response.create_components())).await
+}
+
+// Replaces any current session of the Discord user with a new one for the authentication request
+async fn grant_session(db: &Database, discord_user: u64, request_id: i32, session_minutes: u32) -> Result<(), DatabaseError> {
+    let _ = db.delete_player_auth(&discord_user.to_string()).await;
+    db.add_player_auth(&discord_user.to_string(), &request_id, &(session_minutes as i32)).await
+}
+
+// Discord users with a login request in progress, along with the requests they made in the meantime
+struct InProgress {
+    users: Mutex<HashMap<u64, Vec<i32>>>,
+    requeue: UnboundedSender<i32>,
+}
+
+impl InProgress {
+    // None if the Discord user already has a login request in progress. The request is then queued
+    // again once that one is done, so that it is prompted for instead of being left pending.
+    fn enter(&self, discord_user: u64, request_id: i32) -> Option<InProgressGuard<'_>> {
+        let mut users = self.users.lock().unwrap();
+
+        if let Some(deferred) = users.get_mut(&discord_user) {
+            deferred.push(request_id);
+            return None;
+        }
+
+        users.insert(discord_user, Vec::new());
+        Some(InProgressGuard { in_progress: self, discord_user })
+    }
+}
+
+// Marks a Discord user as having a login request in progress until dropped
+struct InProgressGuard<'a> {
+    in_progress: &'a InProgress,
+    discord_user: u64,
+}
+
+impl Drop for InProgressGuard<'_> {
+    fn drop(&mut self) {
+        let deferred = self.in_progress.users.lock().unwrap().remove(&self.discord_user).unwrap_or_default();
+
+        for request_id in deferred {
+            if self.in_progress.requeue.unbounded_send(request_id).is_err() {
+                println!("Could not queue authentication request {} again. The bot is no longer handling authentication requests", request_id);
+            }
+        }
+    }
+}
 
 pub struct AuthenticationHandler {
     db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
-    queue_rx: Arc<Mutex<Receiver<AsyncMessage>>>,
+    pending_authentications: Arc<PendingAuthentications>,
+    settings: AuthenticationSettings,
+    features: FeatureSettings,
+    catalog: Arc<Catalog>,
+    guild_id: GuildId,
+    concurrency_limit: Arc<Semaphore>,
+    in_progress: InProgress,
+    // Taken by handle_authentication_requests, which is the only one receiving the requests queued again
+    requeued: Mutex<Option<UnboundedReceiver<i32>>>,
 }
 
 impl AuthenticationHandler {
-    pub async fn handle_authentication_requests(&self, cache_http: Arc<CacheAndHttp>) {
-        loop {
-            let mut queue_rx = self.queue_rx.lock().unwrap();
-            match &queue_rx.try_next() {
-                Ok(Some(AsyncMessage::Notification(message))) => self.process_authentication(message, Arc::clone(&cache_http)).await,
-                Ok(_) => (),
-                Err(_) => (),
+    pub async fn handle_authentication_requests(self: Arc<Self>, queue_rx: Receiver<i32>, http: Arc<Http>) {
+        let requeued = match self.requeued.lock().unwrap().take() {
+            Some(requeued) => requeued,
+            None => {
+                println!("Authentication requests are already being handled");
+                return
             }
+        };
+
+        // Ends with None once the message queue stops, the requests queued again never end the stream
+        let queue = queue_rx.map(Some).chain(stream::once(futures::future::ready(None)));
+        let mut requests = stream::select(queue, requeued.map(Some));
+
+        while let Some(Some(request_id)) = requests.next().await {
+            let permit = match Arc::clone(&self.concurrency_limit).acquire_owned().await {
+                Ok(permit) => permit,
+                Err(_) => return,
+            };
+            let handler = Arc::clone(&self);
+            let http = Arc::clone(&http);
+
+            tokio::spawn(async move {
+                if let Err(e) = handler.process_authentication(request_id, http).await {
+                    println!("Could not process authentication request {}: {}", request_id, e);
+                }
+
+                drop(permit);
+            });
         }
     }
 
-    async fn process_authentication(&self, message: &Notification, cache_http: Arc<CacheAndHttp>) {
+    async fn process_authentication(&self, request_id: i32, http: Arc<Http>) -> Result<(), Box<dyn Error>> {
         let pool = Arc::clone(&self.db_connection_pool);
-        let db = Database::new(pool).await.unwrap();
+        let db = Database::new(pool).await?;
 
-        let request_id = message.payload().parse::<i32>().unwrap();
-        let minecraft_user = db.get_authentication_request_user(&request_id).await;
-        let ip_address = db.get_authentication_request_ip_address(&request_id).await;
+        let state = match db.is_authentication_request_handled(&request_id).await {
+            Ok(true) => {
+                println!("Ignoring authentication request {}. It has already been handled", request_id);
+                return Ok(())
+            }
+            Ok(false) => match self.prompt_authentication(&db, request_id, http).await {
+                Some(state) => state,
+                None => return Ok(()),
+            },
+            Err(e) => {
+                println!("Could not process authentication request. The request could not be retrieved from Postgres: {}", e);
+                AuthenticationState::FailedDelivery
+            }
+        };
 
-        if let Err(e) = &minecraft_user {
-            println!("Could not process authentication request. User could not be retrieved from Postgres: {}", e);
-        }
+        db.set_authentication_state(&request_id, &state, None).await?;
+
+        Ok(())
+    }
+
+    // None if the Discord user already has a login request in progress. The request stays pending until
+    // that one is done instead of expiring, which would end the session of the player waiting for it.
+    async fn prompt_authentication(&self, db: &Database, request_id: i32, http: Arc<Http>) -> Option<AuthenticationState> {
+        let minecraft_user = match db.get_authentication_request_user(&request_id).await {
+            Ok(minecraft_user) => minecraft_user,
+            Err(e) => {
+                println!("Could not process authentication request. User could not be retrieved from Postgres: {}", e);
+                return Some(AuthenticationState::FailedDelivery)
+            }
+        };
+
+        let discord_user = match db.get_discord_id(&minecraft_user).await {
+            Ok(discord_user) => discord_user,
+            Err(e) => {
+                println!("Could not process authentication request. Discord user could not be retrieved from Postgres: {}", e);
+                return Some(AuthenticationState::FailedDelivery)
+            }
+        };
+
+        let discord_user = match discord_user.parse::<u64>() {
+            Ok(discord_user) => discord_user,
+            Err(e) => {
+                println!("Could not process authentication request. Discord user could not be parsed: {}", e);
+                return Some(AuthenticationState::FailedDelivery)
+            }
+        };
+
+        // Taken before any other query so that replayed requests do not each look up the same player
+        let _in_progress = match self.in_progress.enter(discord_user, request_id) {
+            Some(guard) => guard,
+            None => {
+                println!("Postponing authentication request {}. The Discord user {} already has a login request in progress", request_id, discord_user);
+                return None
+            }
+        };
+
+        Some(self.authenticate(db, request_id, &minecraft_user, discord_user, http).await)
+    }
+
+    async fn authenticate(&self, db: &Database, request_id: i32, minecraft_user: &str, discord_user: u64, http_client: Arc<Http>) -> AuthenticationState {
+        let ip_address = db.get_authentication_request_ip_address(&request_id).await;
+        let minecraft_server = db.get_authentication_request_server(&request_id).await;
 
         if let Err(e) = &ip_address {
             println!("Could not process authentication request. Ip address could not be retrieved from Postgres: {}", e);
         }
 
-        if minecraft_user.is_err() || ip_address.is_err() {
-            return
+        if let Err(e) = &minecraft_server {
+            println!("Could not process authentication request. Minecraft server could not be retrieved from Postgres: {}", e);
+        }
+
+        if ip_address.is_err() || minecraft_server.is_err() {
+            return AuthenticationState::FailedDelivery
         }
 
-        let minecraft_user = minecraft_user.unwrap();
         let ip_address = ip_address.unwrap();
+        let minecraft_server = db.get_or_add_minecraft_server(&minecraft_server.unwrap()).await;
+
+        if let Err(e) = &minecraft_server {
+            println!("Could not process authentication request. Minecraft server rules could not be retrieved from Postgres: {}", e);
+            return AuthenticationState::FailedDelivery
+        }
 
-        let discord_user = db.get_discord_id(&minecraft_user).await;
+        let minecraft_server = minecraft_server.unwrap();
+        let mut settings = self.settings.for_server(&minecraft_server.name);
 
-        if let Err(e) = &discord_user {
-            println!("Could not process authentication request. Discord user could not be retrieved from Postgres: {}", e);
-            return
+        // The session length stored for the server with the server command wins over the configured one
+        if let Some(session_minutes) = minecraft_server.session_minutes {
+            settings.session_minutes = session_minutes as u32;
         }
 
-        let discord_user = discord_user.unwrap();
-        let discord_user = discord_user.parse::<u64>();
+        if minecraft_server.maintenance {
+            println!("Denying authentication request {}. The Minecraft server {} is in maintenance mode", request_id, minecraft_server.name);
+            return AuthenticationState::Denied
+        }
+
+        match db.is_player_locked(&discord_user.to_string()).await {
+            Ok(false) => (),
+            Ok(true) => {
+                println!("Denying authentication request {}. The account of the Discord user {} is locked", request_id, discord_user);
+                return AuthenticationState::Denied
+            }
+            Err(e) => {
+                println!("Could not process authentication request. Could not check whether the Discord user {} is locked: {}", discord_user, e);
+                return AuthenticationState::FailedDelivery
+            }
+        }
 
-        if let Err(e) = &discord_user {
-            println!("Could not process authentication request. Discord user could not be parsed: {}", e);
-            return
+        let http = http_client.as_ref();
+
+        if let Some(required_role_id) = &minecraft_server.required_role_id {
+            match self.has_role(http, discord_user, required_role_id).await {
+                Ok(true) => (),
+                Ok(false) => {
+                    println!("Denying authentication request {}. The Discord user {} does not have the role required by the Minecraft server {}", request_id, discord_user, minecraft_server.name);
+                    return AuthenticationState::Denied
+                }
+                Err(e) => {
+                    println!("Could not process authentication request. Could not retrieve the roles of the Discord user {}: {}", discord_user, e);
+                    return AuthenticationState::FailedDelivery
+                }
+            }
+        }
+
+        if !minecraft_server.require_approval {
+            return match grant_session(db, discord_user, request_id, settings.session_minutes).await {
+                Ok(()) => {
+                    println!("Approved authentication request {}. The Minecraft server {} does not require approval", request_id, minecraft_server.name);
+                    AuthenticationState::Approved
+                }
+                Err(e) => {
+                    println!("Could not process authentication request. Could not add new authentication for the Discord user {}: {}", discord_user, e);
+                    AuthenticationState::FailedDelivery
+                }
+            }
         }
 
-        let discord_user = discord_user.unwrap();
-        let http = cache_http.http();
         let user = http.get_user(discord_user).await;
 
         if let Err(e) = &user {
             println!("Could not process authentication request. User could not be fetched from Discord servers: {}", e);
-            return
+            return AuthenticationState::FailedDelivery
         }
 
         let user = user.unwrap();
-        let channel = user.create_dm_channel(&cache_http).await;
+        let channel = user.create_dm_channel(&http_client).await;
 
         if let Err(e) = &channel {
             println!("Could not process authentication request. Could not create a DM channel with the user {}: {}", user.name, e);
-            return
+            return AuthenticationState::FailedDelivery
         }
 
         let channel = channel.unwrap();
-        let message = channel.send_message(http, |c| c.add_embed(|e| e.title("Minecraft login").description(format!("The Minecraft user {} tried to login on the Minecraft server. Was it you?", minecraft_user)))).await;
+        let locale = &self.user_locale(db, discord_user).await;
+        let catalog = &self.catalog;
+
+        let trusted = if self.features.trusted_networks {
+            db.is_trusted_address(&discord_user.to_string(), &ip_address).await
+        } else {
+            Ok(false)
+        };
+
+        match trusted {
+            Ok(true) => {
+                if let Err(e) = grant_session(db, discord_user, request_id, settings.session_minutes).await {
+                    println!("Could not process authentication request. Could not add new authentication for user {}: {}", user.name, e);
+                    return AuthenticationState::FailedDelivery
+                }
 
-        if let Err(e) = message {
-            println!("Could not process authentication request. Could not send a DM to the user {}: {}", user.name, e);
-            return
+                println!("Approved authentication request {}. User {} logged in from the trusted network {}", request_id, user.name, ip_address);
+                let notice = send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.trusted-network", &[("minecraft_user", minecraft_user), ("minecraft_server", &minecraft_server.name), ("ip_address", &ip_address)]), Colour::DARK_GREEN)).await;
+
+                if let Err(e) = notice {
+                    println!("Could not send trusted network notice to the user {}: {}", user.name, e);
+                }
+
+                return AuthenticationState::Approved
+            }
+            Ok(false) => (),
+            Err(e) => println!("Could not check whether {} is a trusted network for user {}: {}", ip_address, user.name, e),
         }
 
-        let message = message.unwrap();
-        let yes_reaction = message.react(&cache_http, '✅').await;
+        let rate_limit_window = self.settings.rate_limit_window_seconds as i32;
 
-        if let Err(e) = yes_reaction {
-            println!("Could not process authentication request. Could not react on DM sent to the user {}: {}", user.name, e);
-            return
+        match db.count_recent_prompts(minecraft_user, &ip_address, &rate_limit_window).await {
+            Ok((player_prompts, ip_prompts)) if player_prompts >= self.settings.max_prompts_per_player as i64 || ip_prompts >= self.settings.max_prompts_per_ip as i64 => {
+                println!("Throttling authentication request {}. {} prompts for {} and {} prompts from {} in the last {} seconds", request_id, player_prompts, minecraft_user, ip_prompts, ip_address, rate_limit_window);
+
+                // Only the first throttled request in a window is reported, later ones are blocked silently
+                match db.count_recent_throttled(minecraft_user, &rate_limit_window).await {
+                    Ok(0) => {
+                        let summary = send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.throttled", &[("minecraft_user", minecraft_user), ("ip_address", &ip_address), ("minecraft_server", &minecraft_server.name), ("duration", &catalog.duration(locale, Duration::from_secs(rate_limit_window as u64)))]), Colour::RED)).await;
+
+                        if let Err(e) = summary {
+                            println!("Could not send throttling summary to the user {}: {}", user.name, e);
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(e) => println!("Could not count throttled authentication requests for {}: {}", minecraft_user, e),
+                }
+
+                return AuthenticationState::Throttled
+            }
+            Ok(_) => (),
+            Err(e) => println!("Could not count recent login prompts for {} and {}: {}", minecraft_user, ip_address, e),
         }
 
-        let yes_reaction = yes_reaction.unwrap();
-        let no_reaction = message.react(&cache_http, '❌').await;
+        let response_rx = self.pending_authentications.add(request_id, UserId(discord_user));
+        let mut buttons = vec![response_button(AuthenticationResponse::Approve, request_id, ButtonStyle::Success, catalog.message(locale, "login.approve", &[]))];
 
-        if let Err(e) = no_reaction {
-            println!("Could not process authentication request. Could not react on DM sent to the user {}: {}", user.name, e);
-            return
+        if self.features.trusted_networks {
+            buttons.push(response_button(AuthenticationResponse::Trust, request_id, ButtonStyle::Primary, catalog.message(locale, "login.trust", &[("days", &settings.trusted_network_days.to_string())])));
         }
 
-        let no_reaction = no_reaction.unwrap();
-        let mut user_reaction = None;
-        let start_time = Instant::now();
-        while start_time.elapsed().as_secs() < Duration::from_secs(30).as_secs() {
-            let yes_reactions = message.reaction_users(&http, yes_reaction.emoji.clone(), Option::None, Option::None).await;
-            let no_reactions = message.reaction_users(&http, no_reaction.emoji.clone(), Option::None, Option::None).await;
+        buttons.push(response_button(AuthenticationResponse::Deny, request_id, ButtonStyle::Secondary, catalog.message(locale, "login.deny", &[])));
+        buttons.push(response_button(AuthenticationResponse::Lock, request_id, ButtonStyle::Danger, catalog.message(locale, "login.lock", &[])));
+
+        let prompt = ResponseData {
+            embeds: vec![EmbedData {
+                title: Some(catalog.message(locale, "login.title", &[])),
+                description: Some(catalog.message(locale, "login.prompt", &[("minecraft_user", minecraft_user), ("minecraft_server", &minecraft_server.name), ("duration", &catalog.duration(locale, settings.approval_timeout))])),
+                thumbnail: Some(minecraft_avatar_url(minecraft_user)),
+                timestamp: Some(Timestamp::now()),
+                ..Default::default()
+            }],
+            action_rows: vec![buttons],
+        };
+        let message = send_response(http, channel.id, &prompt).await;
 
-            if let Err(e) = &yes_reactions {
-                println!("Could not process authentication request. Could not read 'yes' reaction on DM sent to the user {}: {}", user.name, e);
-                return
-            }
+        if let Err(e) = message {
+            println!("Could not process authentication request. Could not send a DM to the user {}: {}", user.name, e);
+            self.pending_authentications.remove(request_id);
+            return AuthenticationState::FailedDelivery
+        }
 
-            if let Err(e) = &no_reactions {
-                println!("Could not process authentication request. Could not read 'no' reaction on DM sent to the user {}: {}", user.name, e);
-                return
-            }
+        let mut message = message.unwrap();
 
-            let yes_reactions = yes_reactions.unwrap();
-            let no_reactions = no_reactions.unwrap();
+        if let Err(e) = db.set_authentication_state(&request_id, &AuthenticationState::Prompted, Some(&message.id.to_string())).await {
+            println!("Could not update the state of authentication request {}: {}", request_id, e);
+        }
 
-            if yes_reactions.len() > 1 {
-                println!("User {} reacted with 'yes'!", user.name);
-                user_reaction = Some(&yes_reaction);
-                break;
+        let user_response = match future::timeout(settings.approval_timeout, response_rx).await {
+            Ok(Ok(response)) => {
+                println!("User {} responded with '{:?}'!", user.name, response);
+                Some(response)
             }
-
-            if no_reactions.len() > 1 {
-                println!("User {} reacted with 'no'!", user.name);
-                user_reaction = Some(&no_reaction);
-                break;
+            Ok(Err(_)) | Err(_) => {
+                println!("User {} did not respond to the login request in time", user.name);
+                self.pending_authentications.remove(request_id);
+                None
             }
+        };
 
-            println!("User {} has not reacted yet...", user.name);
-            task::sleep(Duration::from_secs(1)).await;
+        if let Err(e) = message.edit(&http_client, |m| m.components(|c| c)).await {
+            println!("Could not remove buttons from DM sent to the user {}: {}", user.name, e);
         }
 
         let start_time = Instant::now();
         let mut message_confirmation = None;
 
-        if let Some(user_reaction) = user_reaction {
-            if user_reaction.emoji == yes_reaction.emoji {
+        let state = match user_response {
+            Some(response @ (AuthenticationResponse::Approve | AuthenticationResponse::Trust)) => {
+                let mut trust_notice = String::new();
+
+                if response == AuthenticationResponse::Trust {
+                    match db.add_trusted_address(&discord_user.to_string(), &ip_address, &(settings.trusted_network_days as i32)).await {
+                        Ok(()) => trust_notice = catalog.message(locale, "login.trust-notice", &[("ip_address", &ip_address), ("days", &settings.trusted_network_days.to_string())]),
+                        Err(e) => println!("Could not trust the network {} for user {}: {}", ip_address, user.name, e),
+                    }
+                }
+
                 let is_authenticated = db.is_player_authenticated(&discord_user.to_string(), &ip_address).await;
 
                 if let Err(e) = &is_authenticated {
                     println!("Could not process authentication request. Could not retrieve current authentication status for user {}: {}", user.name, e);
-                    return
+                    return AuthenticationState::FailedDelivery
                 }
 
                 let is_authenticated = is_authenticated.unwrap();
 
                 if is_authenticated {
-                    message_confirmation = Some(channel.send_message(http, |c| c.add_embed(|e| e.title("Minecraft login").description("You are already logged in on the Minecraft server.").color(Colour::RED))).await);
+                    message_confirmation = Some(send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.already-logged-in", &[]), Colour::RED)).await);
+                    AuthenticationState::Approved
                 } else {
-                    let _ = db.delete_player_auth(&discord_user.to_string()).await;
-                    let add_auth_result = db.add_player_auth(&discord_user.to_string(), &request_id).await;
+                    let add_auth_result = grant_session(db, discord_user, request_id, settings.session_minutes).await;
 
                     if let Err(e) = add_auth_result {
                         println!("Could not process authentication request. Could not add new authentication for user {}: {}", user.name, e);
-                        message_confirmation = Some(channel.send_message(http, |c| c.add_embed(|e| e.title("Minecraft login").description("An internal error occured. Please try again or contact Mr. Erkaberka if this problem persists!").color(Colour::RED))).await);
+                        message_confirmation = Some(send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.internal-error", &[]), Colour::RED)).await);
+                        AuthenticationState::FailedDelivery
                     } else {
-                        message_confirmation = Some(channel.send_message(http, |c| c.add_embed(|e| e.title("Minecraft login").description("The login request has been approved. You can now join the protected Minecraft servers for the next 30 minutes.").color(Colour::DARK_GREEN))).await);
+                        message_confirmation = Some(send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.approved", &[("duration", &catalog.duration(locale, Duration::from_secs(settings.session_minutes as u64 * 60))), ("trust_notice", &trust_notice)]), Colour::DARK_GREEN)).await);
+                        AuthenticationState::Approved
                     }
                 }
-            } else {
-                message_confirmation = Some(channel.send_message(http, |c| c.add_embed(|e| e.title("Minecraft login").description("The login request has been denied. Contact the Discord moderators if you keep receiving login requests from me.").color(Colour::RED))).await);
             }
-        }
+            Some(AuthenticationResponse::Deny) => {
+                message_confirmation = Some(send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.denied", &[]), Colour::RED)).await);
+                AuthenticationState::Denied
+            }
+            Some(AuthenticationResponse::Lock) => {
+                if let Err(e) = db.set_player_locked(&discord_user.to_string(), &true).await {
+                    println!("Could not lock the account of user {}: {}", user.name, e);
+                }
 
+                if let Err(e) = db.revoke_player_session(&discord_user.to_string()).await {
+                    println!("Could not revoke the session of user {}: {}", user.name, e);
+                }
 
-        while start_time.elapsed().as_secs() < Duration::from_secs(30).as_secs() {
-            task::sleep(Duration::from_secs(1)).await;
-        }
+                self.alert_moderators(http, discord_user, minecraft_user, &ip_address, &minecraft_server.name).await;
+                message_confirmation = Some(send_response(http, channel.id, &self.login_response(locale, catalog.message(locale, "login.locked", &[]), Colour::RED)).await);
+                AuthenticationState::Denied
+            }
+            None => AuthenticationState::Expired,
+        };
 
-        if let Err(e) = message.delete(&cache_http).await {
-            println!("An error occured when deleting initial DM to user {}: {}", user.name, e);
-        }
 
-        if let Some(message_confirmation) = message_confirmation {
-            if let Err(e) = message_confirmation {
-                println!("Could not process authentication request. Could not send DM confirmation to user {}: {}", user.name, e);
-                return
+        // Clean up the DMs in the background so the outcome can be recorded right away
+        tokio::spawn(async move {
+            task::sleep(settings.message_deletion.saturating_sub(start_time.elapsed())).await;
+
+            if let Err(e) = message.delete(&http_client).await {
+                println!("An error occured when deleting initial DM to user {}: {}", user.name, e);
             }
 
-            let message_confirmation = message_confirmation.unwrap();
+            if let Some(message_confirmation) = message_confirmation {
+                if let Err(e) = message_confirmation {
+                    println!("Could not process authentication request. Could not send DM confirmation to user {}: {}", user.name, e);
+                    return
+                }
+
+                let message_confirmation = message_confirmation.unwrap();
 
-            if let Err(e) = message_confirmation.delete(&cache_http).await {
-                println!("An error occured when deleting DM confirmation to user {}: {}", user.name, e);
+                if let Err(e) = message_confirmation.delete(&http_client).await {
+                    println!("An error occured when deleting DM confirmation to user {}: {}", user.name, e);
+                }
+            }
+        });
+
+        state
+    }
+
+    // The language chosen with /language, otherwise the default language of the guild
+    async fn user_locale(&self, db: &Database, discord_user: u64) -> String {
+        match db.get_locale(&discord_user.to_string()).await {
+            Ok(Some(locale)) => locale,
+            Ok(None) => self.catalog.default_language().to_string(),
+            Err(e) => {
+                println!("Could not retrieve the language of the Discord user {}: {}", discord_user, e);
+                self.catalog.default_language().to_string()
             }
         }
     }
 
+    fn login_response(&self, locale: &str, description: String, colour: Colour) -> ResponseData {
+        EmbedData {
+            title: Some(self.catalog.message(locale, "login.title", &[])),
+            description: Some(description),
+            colour: Some(colour),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    async fn alert_moderators(&self, http: &Http, discord_user: u64, minecraft_user: &str, ip_address: &str, minecraft_server: &str) {
+        let channel = match self.settings.moderator_channel_id {
+            Some(channel) => ChannelId(channel),
+            None => return,
+        };
+
+        // Moderators share the channel, so the alert is in the default language
+        let catalog = &self.catalog;
+        let locale = catalog.default_language();
+        let alert = EmbedData {
+            title: Some(catalog.message(locale, "alert.title", &[])),
+            description: Some(catalog.message(locale, "alert.description", &[("discord_id", &discord_user.to_string())])),
+            colour: Some(Colour::RED),
+            thumbnail: Some(minecraft_avatar_url(minecraft_user)),
+            timestamp: Some(Timestamp::now()),
+            ..Default::default()
+        }
+        .field(catalog.message(locale, "alert.minecraft-user", &[]), minecraft_user, true)
+        .field(catalog.message(locale, "alert.ip-address", &[]), format!("`{}`", ip_address), true)
+        .field(catalog.message(locale, "alert.minecraft-server", &[]), minecraft_server, true);
+        let alert = send_response(http, channel, &alert.into()).await;
+
+        if let Err(e) = alert {
+            println!("Could not send the account lock alert for {} to the moderators: {}", minecraft_user, e);
+        }
+    }
+
+    async fn has_role(&self, http: &Http, discord_user: u64, role_id: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let role_id = RoleId(role_id.parse::<u64>()?);
+        let member = http.get_member(self.guild_id.0, discord_user).await?;
+
+        Ok(member.roles.contains(&role_id))
+    }
+
     pub fn new(
         db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
-        queue_rx: Receiver<AsyncMessage>
+        pending_authentications: Arc<PendingAuthentications>,
+        settings: AuthenticationSettings,
+        features: FeatureSettings,
+        catalog: Arc<Catalog>,
+        guild_id: GuildId,
         ) -> AuthenticationHandler {
-        AuthenticationHandler { db_connection_pool, queue_rx: Arc::new(Mutex::new(queue_rx)) }
+        let (requeue, requeued) = mpsc::unbounded();
+
+        AuthenticationHandler {
+            db_connection_pool,
+            pending_authentications,
+            concurrency_limit: Arc::new(Semaphore::new(settings.max_concurrent)),
+            settings,
+            features,
+            catalog,
+            guild_id,
+            in_progress: InProgress {
+                users: Mutex::new(HashMap::new()),
+                requeue,
+            },
+            requeued: Mutex::new(Some(requeued)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::test_database::TestDatabase;
+    use crate::services::database::MinecraftServer;
+    use crate::services::localization::test_catalog;
+    use futures::SinkExt;
+
+    fn settings() -> AuthenticationSettings {
+        AuthenticationSettings {
+            session_minutes: 30,
+            approval_timeout_seconds: 60,
+            message_deletion_seconds: 60,
+            trusted_network_days: 30,
+            rate_limit_window_seconds: 600,
+            max_prompts_per_player: 5,
+            max_prompts_per_ip: 10,
+            moderator_channel_id: None,
+            max_concurrent: 4,
+            servers: HashMap::new(),
+        }
+    }
+
+    async fn states(database: &TestDatabase) -> Vec<(String, bool)> {
+        let connection = database.pool.get().await.unwrap();
+        let rows = connection
+            .query("SELECT state, handled FROM AuthenticationRequests ORDER BY id", &[])
+            .await
+            .unwrap();
+
+        rows.iter().map(|r| (r.get("state"), r.get("handled"))).collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn postponed_requests_of_a_user_reach_a_final_state() {
+        let database = TestDatabase::new().await;
+        let db = Database::new(Arc::clone(&database.pool)).await.unwrap();
+        db.add_player("1001", "CODE", &15).await.unwrap();
+        db.set_minecraft_user("1001", "Steve").await.unwrap();
+
+        // Denies the requests before anything is sent to Discord
+        db.set_minecraft_server(&MinecraftServer {
+            name: "lobby".to_string(),
+            required_role_id: None,
+            session_minutes: None,
+            require_approval: true,
+            maintenance: true,
+        })
+        .await
+        .unwrap();
+
+        let handler = Arc::new(AuthenticationHandler::new(
+            Arc::clone(&database.pool),
+            Arc::new(PendingAuthentications::new()),
+            settings(),
+            FeatureSettings {
+                trusted_networks: false,
+                admin_commands: false,
+            },
+            Arc::new(test_catalog::catalog()),
+            GuildId(1),
+        ));
+        let (mut queue_tx, queue_rx) = mpsc::channel(2);
+        tokio::spawn(Arc::clone(&handler).handle_authentication_requests(queue_rx, Arc::new(Http::new(""))));
+
+        // Another login request of the user is waiting for an answer
+        let prompt = handler.in_progress.enter(1001, 0).unwrap();
+
+        for _ in 0..2 {
+            let request_id = db.add_authentication_request("Steve", "127.0.0.1", "lobby").await.unwrap();
+            queue_tx.send(request_id).await.unwrap();
+        }
+
+        while handler.in_progress.users.lock().unwrap()[&1001].len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let postponed = states(&database).await;
+        drop(prompt);
+
+        let mut resolved = states(&database).await;
+
+        for _ in 0..500 {
+            if resolved.iter().all(|(_, handled)| *handled) {
+                break;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            resolved = states(&database).await;
+        }
+
+        database.drop().await;
+
+        assert_eq!(postponed, vec![("pending".to_string(), false); 2]);
+        assert_eq!(resolved, vec![("denied".to_string(), true); 2]);
     }
 }
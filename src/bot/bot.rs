@@ -1,5 +1,9 @@
 use serenity::async_trait;
 use serenity::framework::StandardFramework;
+use serenity::http::Http;
+use serenity::model::application::command::Command as ApplicationCommand;
+use serenity::model::application::interaction::autocomplete::AutocompleteInteraction;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
 use serenity::model::application::interaction::{Interaction, InteractionResponseType};
 use serenity::model::gateway::Activity;
 use serenity::model::gateway::Ready;
@@ -11,129 +15,335 @@ use serenity::prelude::GatewayIntents;
 use serenity::utils::Colour;
 use serenity::Error;
 
-use tokio::runtime::Runtime;
-use tokio_postgres::{AsyncMessage, NoTls};
+use tokio_postgres::NoTls;
 
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
 
-use std::cell::RefCell;
-use std::{env, thread};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use crate::bot::command::SlashCommand;
+use crate::bot::arguments::{get_focused_option, ArgumentError, CommandArguments};
+use crate::bot::command::{SlashCommand, MAX_AUTOCOMPLETE_CHOICES};
 use crate::bot::commands;
+use crate::services::database::Database;
 use crate::services::embed::EmbedData;
+use crate::services::localization::{Catalog, MessageParameters};
+use crate::services::settings::Settings;
 
 use futures::channel::mpsc::Receiver;
 
-use super::authentication::AuthenticationHandler;
+use super::authentication::{AuthenticationHandler, AuthenticationResponse, PendingAuthentications};
+
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("arguments.unknown-subcommand", &["name"]),
+    ("arguments.unknown-option", &["name"]),
+    ("arguments.missing-option", &["name"]),
+    ("arguments.invalid-option", &["name"]),
+    ("arguments.invalid-choice", &["name"]),
+];
 
 struct Handler {
     commands: Vec<Arc<Box<dyn SlashCommand + 'static>>>,
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    pending_authentications: Arc<PendingAuthentications>,
+    catalog: Arc<Catalog>,
+    guild_id: GuildId,
 }
 
 pub struct Bot {
-    authentication_handler: Arc<Mutex<AuthenticationHandler>>,
-    client: RefCell<Client>,
+    authentication_handler: Arc<AuthenticationHandler>,
+    client: Client,
+    queue_receiver: Receiver<i32>,
 }
 
 impl Bot {
     pub async fn new(
-        token: String,
+        settings: &Settings,
         db_connection_pool: Pool<PostgresConnectionManager<NoTls>>,
-        queue_receiver: Receiver<AsyncMessage>,
+        queue_receiver: Receiver<i32>,
+        catalog: Catalog,
     ) -> Result<Bot, Box<dyn std::error::Error>> {
         let pool = Arc::new(db_connection_pool);
+        let pending_authentications = Arc::new(PendingAuthentications::new());
+        let catalog = Arc::new(catalog);
+        let discord = settings.discord()?;
+        let guild_id = GuildId(discord.guild_id);
         let framework = StandardFramework::new();
         let handler = Handler {
-            commands: commands::get_commands(Arc::clone(&pool)),
+            commands: commands::get_commands(Arc::clone(&pool), settings, Arc::clone(&catalog)),
+            db_connection_pool: Arc::clone(&pool),
+            pending_authentications: Arc::clone(&pending_authentications),
+            catalog: Arc::clone(&catalog),
+            guild_id,
         };
 
-        let client = Client::builder(token, GatewayIntents::empty())
+        let client = Client::builder(&discord.token, GatewayIntents::empty())
             .event_handler(handler)
             .framework(framework)
             .await?;
 
         let bot = Bot {
-            client: RefCell::new(client),
-            authentication_handler: Arc::new(Mutex::new(AuthenticationHandler::new(Arc::clone(&pool), queue_receiver)))
+            client,
+            authentication_handler: Arc::new(AuthenticationHandler::new(
+                Arc::clone(&pool),
+                pending_authentications,
+                settings.authentication.clone(),
+                settings.features.clone(),
+                catalog,
+                guild_id,
+            )),
+            queue_receiver,
         };
 
         Ok(bot)
     }
 
-    pub async fn start(&self) -> Result<(), Error> {
-        let cache_http = Arc::clone(&self.client.borrow().cache_and_http);
-        let auth_handler = Arc::clone(&self.authentication_handler.clone());
+    pub async fn start(self) -> Result<(), Error> {
+        let Bot {
+            authentication_handler,
+            mut client,
+            queue_receiver,
+        } = self;
+        let http = Arc::clone(&client.cache_and_http.http);
 
-        thread::spawn(move || {
-            let rt = Runtime::new().unwrap();
-            loop {
-                let cache_http = Arc::clone(&cache_http.clone());
-                let auth_handler = auth_handler.lock().unwrap();
-                rt.block_on(auth_handler.handle_authentication_requests(cache_http));
-            }
-        });
+        tokio::spawn(authentication_handler.handle_authentication_requests(queue_receiver, http));
 
-        let mut client = self.client.borrow_mut();
         client.start().await
     }
 }
 
+fn error_embed(title: String, description: String) -> EmbedData {
+    EmbedData {
+        title: Some(title),
+        description: Some(description),
+        colour: Some(Colour::RED),
+        ..Default::default()
+    }
+}
+
+// Replaces the slash commands of the guild with the given ones
+pub async fn register_commands(
+    http: &Http,
+    guild_id: GuildId,
+    commands: &[Arc<Box<dyn SlashCommand + 'static>>],
+    catalog: &Catalog,
+) -> Result<Vec<ApplicationCommand>, Error> {
+    GuildId::set_application_commands(&guild_id, http, |mut builder| {
+        for command in commands.iter() {
+            builder = builder.create_application_command(|c| {
+                let cmd = Arc::clone(command);
+                cmd.register(c, catalog)
+            });
+        }
+
+        builder
+    })
+    .await
+}
+
+fn argument_error_message(catalog: &Catalog, locale: &str, error: &ArgumentError) -> String {
+    let (id, name) = match error {
+        ArgumentError::MissingSubcommand => ("arguments.missing-subcommand", ""),
+        ArgumentError::UnknownSubcommand(name) => ("arguments.unknown-subcommand", name.as_str()),
+        ArgumentError::UnknownOption(name) => ("arguments.unknown-option", name.as_str()),
+        ArgumentError::MissingOption(name) => ("arguments.missing-option", name.as_str()),
+        ArgumentError::InvalidOption(name) => ("arguments.invalid-option", name.as_str()),
+        ArgumentError::InvalidChoice(name) => ("arguments.invalid-choice", name.as_str()),
+    };
+
+    catalog.message(locale, id, &[("name", name)])
+}
+
+impl Handler {
+    async fn handle_autocomplete(&self, ctx: &Context, autocomplete: AutocompleteInteraction) {
+        let command = match self
+            .commands
+            .iter()
+            .find(|&c| c.name() == autocomplete.data.name.as_str())
+        {
+            Some(command) => command,
+            None => return,
+        };
+        let focused = match get_focused_option(&autocomplete.data.options) {
+            Some(focused) => focused,
+            None => return,
+        };
+        let value = focused.value.as_ref().and_then(|v| v.as_str()).unwrap_or_default();
+
+        let choices = match command.autocomplete(&focused.name, value).await {
+            Ok(choices) => choices,
+            Err(e) => {
+                println!(
+                    "An error occured when autocompleting the option '{}' of the command '{}': {}",
+                    focused.name,
+                    command.name(),
+                    e
+                );
+                Vec::new()
+            }
+        };
+
+        if let Err(why) = autocomplete
+            .create_autocomplete_response(&ctx.http, |response| {
+                for choice in choices.iter().take(MAX_AUTOCOMPLETE_CHOICES) {
+                    response.add_string_choice(choice, choice);
+                }
+
+                response
+            })
+            .await
+        {
+            println!("Cannot respond to autocomplete: {}", why);
+        }
+    }
+
+    // The language chosen with /language, otherwise the language of the user's Discord client
+    async fn component_locale(&self, component: &MessageComponentInteraction) -> String {
+        let database = Database::new(Arc::clone(&self.db_connection_pool))
+            .await
+            .map_err(|e| e.to_string());
+        let locale = match database {
+            Ok(database) => database
+                .get_locale(&component.user.id.to_string())
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+
+        match locale {
+            Ok(locale) => locale.unwrap_or_else(|| component.locale.clone()),
+            Err(e) => {
+                println!("Could not retrieve the language of the Discord user {}: {}", component.user.id, e);
+                component.locale.clone()
+            }
+        }
+    }
+
+    async fn handle_component(&self, ctx: &Context, component: MessageComponentInteraction) {
+        let resolved = match AuthenticationResponse::from_custom_id(&component.data.custom_id) {
+            Some((response, request_id)) => {
+                self.pending_authentications
+                    .resolve(request_id, component.user.id, response)
+            }
+            None => false,
+        };
+
+        let result = if resolved {
+            component
+                .create_interaction_response(&ctx.http, |response| {
+                    response.kind(InteractionResponseType::DeferredUpdateMessage)
+                })
+                .await
+        } else {
+            let locale = &self.component_locale(&component).await;
+            let catalog = &self.catalog;
+
+            component
+                .create_interaction_response(&ctx.http, |response| {
+                    response
+                        .kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|message| {
+                            message.ephemeral(true).embed(|e| {
+                                e.title(catalog.message(locale, "login.title", &[]))
+                                    .description(catalog.message(locale, "login.no-longer-valid", &[]))
+                                    .colour(Colour::RED)
+                            })
+                        })
+                })
+                .await
+        };
+
+        if let Err(why) = result {
+            println!("Cannot respond to message component: {}", why);
+        }
+    }
+}
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::ApplicationCommand(command) = interaction {
-            println!("Received command interaction: {:#?}", command);
+        if let Interaction::Autocomplete(autocomplete) = interaction {
+            self.handle_autocomplete(&ctx, autocomplete).await;
+        } else if let Interaction::MessageComponent(component) = interaction {
+            println!("Received component interaction: {:#?}", component.data);
+            self.handle_component(&ctx, component).await;
+        } else if let Interaction::ApplicationCommand(command) = interaction {
+            // The options and the response are not logged, they can contain registration codes
+            println!("Received the command '{}' from the Discord user {}", command.data.name, command.user.id);
 
             if let Some(c) = self
                 .commands
                 .iter()
                 .find(|&c| c.name() == command.data.name.as_str())
             {
-                let content = match c.run(&ctx, &command.data.options).await {
-                    Ok(result) => result,
-                    Err(e) => {
-                        println!(
-                            "An error occured when executing the command '{}': {}",
-                            c.name(),
-                            e
-                        );
-                        EmbedData {
-                            title: Some(c.name()),
-                            description: Some("An error occured, please try again".to_string()),
-                            colour: Some(Colour::RED),
+                // Deferring acknowledges the interaction right away, the response is filled in once the
+                // command has finished
+                if c.defer() {
+                    if let Err(why) = command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::DeferredChannelMessageWithSource)
+                                .interaction_response_data(|message| message.ephemeral(c.ephemeral()))
+                        })
+                        .await
+                    {
+                        println!("Cannot defer response to slash command: {}", why);
+                        return;
+                    }
+                }
+
+                let arguments = CommandArguments::parse(
+                    &c.options(),
+                    &c.subcommands(),
+                    &c.subcommand_groups(),
+                    &command.data.options,
+                );
+                let content = match arguments {
+                    Ok(arguments) => match c.run(&ctx, &command, &arguments).await {
+                        Ok(result) => result,
+                        Err(e) => {
+                            println!(
+                                "An error occured when executing the command '{}': {}",
+                                c.name(),
+                                e
+                            );
+                            error_embed(c.name(), self.catalog.message(&command.locale, "error", &[])).into()
                         }
+                    },
+                    Err(e) => {
+                        println!("Invalid options for the command '{}': {}", c.name(), e);
+                        error_embed(c.name(), argument_error_message(&self.catalog, &command.locale, &e)).into()
                     }
                 };
 
-                println!("Responding with content '{:?}'", content);
-
-                if let Err(why) = command
-                    .create_interaction_response(&ctx.http, |response| {
-                        response
-                            .kind(InteractionResponseType::ChannelMessageWithSource)
-                            .interaction_response_data(|message| {
-                                message.embed(|mut e| {
-                                    if let Some(title) = content.title {
-                                        e = e.title(title);
-                                    };
-
-                                    if let Some(description) = content.description {
-                                        e = e.description(description);
-                                    };
-
-                                    if let Some(colour) = content.colour {
-                                        e = e.colour(colour);
-                                    };
-
-                                    e
+                let result = if c.defer() {
+                    command
+                        .edit_original_interaction_response(&ctx.http, |response| {
+                            response
+                                .set_embeds(content.create_embeds())
+                                .components(|c| {
+                                    *c = content.create_components();
+                                    c
                                 })
-                            })
-                    })
-                    .await
-                {
+                        })
+                        .await
+                        .map(|_| ())
+                } else {
+                    command
+                        .create_interaction_response(&ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|message| {
+                                    message
+                                        .ephemeral(c.ephemeral())
+                                        .set_embeds(content.create_embeds())
+                                        .set_components(content.create_components())
+                                })
+                        })
+                        .await
+                };
+
+                if let Err(why) = result {
                     println!("Cannot respond to slash command: {}", why);
                 }
             };
@@ -143,24 +353,7 @@ impl EventHandler for Handler {
     async fn ready(&self, ctx: Context, ready: Ready) {
         println!("{} is connected!", ready.user.name);
 
-        let guild_id = GuildId(
-            env::var("GUILD_ID")
-                .expect("Expected GUILD_ID in environment")
-                .parse()
-                .expect("GUILD_ID must be an integer"),
-        );
-
-        let commands = GuildId::set_application_commands(&guild_id, &ctx.http, |mut commands| {
-            for command in self.commands.iter() {
-                commands = commands.create_application_command(|c| {
-                    let cmd = Arc::clone(command);
-                    cmd.register(c)
-                });
-            }
-
-            commands
-        })
-        .await;
+        let commands = register_commands(&ctx.http, self.guild_id, &self.commands, &self.catalog).await;
 
         println!(
             "I now have the following guild slash commands: {:#?}",
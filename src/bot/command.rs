@@ -1,24 +1,211 @@
 use async_trait::async_trait;
-use serenity::builder::CreateApplicationCommand;
-use serenity::model::prelude::interaction::application_command::CommandDataOption;
+use serenity::builder::{CreateApplicationCommand, CreateApplicationCommandOption};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::Permissions;
 use serenity::prelude::Context;
 use std::error::Error;
 
-use crate::services::embed::EmbedData;
+use crate::bot::arguments::{CommandArguments, OptionValue};
+use crate::services::embed::ResponseData;
+use crate::services::localization::Catalog;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    String,
+    Integer,
+    Boolean,
+    User,
+}
+
+impl OptionKind {
+    fn command_option_type(&self) -> CommandOptionType {
+        match self {
+            OptionKind::String => CommandOptionType::String,
+            OptionKind::Integer => CommandOptionType::Integer,
+            OptionKind::Boolean => CommandOptionType::Boolean,
+            OptionKind::User => CommandOptionType::User,
+        }
+    }
+}
+
+pub struct OptionChoice {
+    pub name: String,
+    pub value: OptionValue,
+}
+
+pub struct CommandOption {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: OptionKind,
+    pub required: bool,
+    // When not empty, only these values are accepted
+    pub choices: Vec<OptionChoice>,
+    // Suggestions are requested from SlashCommand::autocomplete while the user is typing
+    pub autocomplete: bool,
+}
+
+pub struct SubCommand {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub options: Vec<CommandOption>,
+}
+
+pub struct SubCommandGroup {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub subcommands: Vec<SubCommand>,
+}
+
+// The most suggestions Discord accepts in an autocomplete response
+pub static MAX_AUTOCOMPLETE_CHOICES: usize = 25;
 
 #[async_trait]
 pub trait SlashCommand: Send + Sync {
     fn description(&self) -> String;
     fn name(&self) -> String;
+    // Ephemeral responses are only shown to the user who invoked the command
+    fn ephemeral(&self) -> bool {
+        false
+    }
+    // Commands that may take longer than the 3 seconds Discord waits for a response acknowledge the
+    // interaction first and edit the response afterwards
+    fn defer(&self) -> bool {
+        false
+    }
+    // Options of a command without subcommands
+    fn options(&self) -> Vec<CommandOption> {
+        Vec::new()
+    }
+    fn subcommands(&self) -> Vec<SubCommand> {
+        Vec::new()
+    }
+    fn subcommand_groups(&self) -> Vec<SubCommandGroup> {
+        Vec::new()
+    }
+    // Members without these permissions do not see the command unless a server admin allows them to
+    fn default_member_permissions(&self) -> Option<Permissions> {
+        None
+    }
+    // Names and descriptions are translated from the [commands] tables of the message bundles
     fn register<'a>(
         &self,
         command: &'a mut CreateApplicationCommand,
+        catalog: &Catalog,
     ) -> &'a mut CreateApplicationCommand {
-        command.name(self.name()).description(self.description())
+        let name = self.name();
+        command.name(&name).description(self.description());
+
+        for (locale, translation) in catalog.command_translations(&format!("{}.name", name)) {
+            command.name_localized(locale, translation);
+        }
+
+        for (locale, translation) in catalog.command_translations(&format!("{}.description", name)) {
+            command.description_localized(locale, translation);
+        }
+
+        if let Some(permissions) = self.default_member_permissions() {
+            command.default_member_permissions(permissions).dm_permission(false);
+        }
+
+        for option in self.options() {
+            command.create_option(|o| register_option(o, &option, catalog, &name));
+        }
+
+        for subcommand in self.subcommands() {
+            command.create_option(|o| register_subcommand(o, &subcommand, catalog, &name));
+        }
+
+        for group in self.subcommand_groups() {
+            command.create_option(|o| {
+                let path = format!("{}.{}", name, group.name);
+                o.name(group.name)
+                    .description(group.description)
+                    .kind(CommandOptionType::SubCommandGroup);
+                localize(o, catalog, &path);
+
+                for subcommand in group.subcommands.iter() {
+                    o.create_sub_option(|s| register_subcommand(s, subcommand, catalog, &path));
+                }
+
+                o
+            });
+        }
+
+        command
     }
+    // The options of the interaction have already been checked against the definitions above
     async fn run(
         &self,
         ctx: &Context,
-        options: &[CommandDataOption],
-    ) -> Result<EmbedData, Box<dyn Error>>;
+        command: &ApplicationCommandInteraction,
+        arguments: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>>;
+    // Suggestions for the value the user has typed so far in an option with autocomplete enabled
+    async fn autocomplete(
+        &self,
+        _option: &str,
+        _value: &str,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+}
+
+fn register_subcommand<'a>(
+    builder: &'a mut CreateApplicationCommandOption,
+    subcommand: &SubCommand,
+    catalog: &Catalog,
+    parent: &str,
+) -> &'a mut CreateApplicationCommandOption {
+    let path = format!("{}.{}", parent, subcommand.name);
+    builder
+        .name(subcommand.name)
+        .description(subcommand.description)
+        .kind(CommandOptionType::SubCommand);
+    localize(builder, catalog, &path);
+
+    for option in subcommand.options.iter() {
+        builder.create_sub_option(|s| register_option(s, option, catalog, &path));
+    }
+
+    builder
+}
+
+fn register_option<'a>(
+    builder: &'a mut CreateApplicationCommandOption,
+    option: &CommandOption,
+    catalog: &Catalog,
+    parent: &str,
+) -> &'a mut CreateApplicationCommandOption {
+    builder
+        .name(option.name)
+        .description(option.description)
+        .kind(option.kind.command_option_type())
+        .required(option.required);
+    localize(builder, catalog, &format!("{}.{}", parent, option.name));
+
+    if option.autocomplete {
+        builder.set_autocomplete(true);
+    }
+
+    for choice in option.choices.iter() {
+        match &choice.value {
+            OptionValue::String(value) => builder.add_string_choice(&choice.name, value),
+            OptionValue::Integer(value) => builder.add_int_choice(&choice.name, *value as i32),
+            // Discord only supports choices for strings and numbers
+            OptionValue::Boolean(_) | OptionValue::User(_) => builder,
+        };
+    }
+
+    builder
+}
+
+fn localize(builder: &mut CreateApplicationCommandOption, catalog: &Catalog, path: &str) {
+    for (locale, translation) in catalog.command_translations(&format!("{}.name", path)) {
+        builder.name_localized(locale, translation);
+    }
+
+    for (locale, translation) in catalog.command_translations(&format!("{}.description", path)) {
+        builder.description_localized(locale, translation);
+    }
 }
@@ -0,0 +1,549 @@
+use std::sync::Arc;
+
+use crate::services::database::Database;
+use crate::services::database::DatabaseError;
+use crate::services::embed::{minecraft_avatar_url, EmbedData, ResponseData};
+use crate::services::localization::{Catalog, MessageParameters};
+use crate::services::registration_code::{store_unique_code, CodeGenerator, LongCodeGenerator};
+use crate::services::settings::RegistrationSettings;
+
+use super::super::arguments::CommandArguments;
+use super::super::command::{CommandOption, OptionKind, SlashCommand, SubCommand, MAX_AUTOCOMPLETE_CHOICES};
+use super::register::registration_instructions;
+use super::status::describe_state;
+use super::user_locale;
+use async_trait::async_trait;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::id::UserId;
+use serenity::model::Permissions;
+use serenity::prelude::Context;
+use serenity::utils::Colour;
+
+use std::error::Error;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+static NAME: &str = "admin";
+static DESCRIPTION: &str = "Manage the Minecraft accounts of other Discord users";
+static LOOKUP: &str = "lookup";
+static INVESTIGATE: &str = "investigate";
+static UNLOCK: &str = "unlock";
+static FORCE_UNREGISTER: &str = "force-unregister";
+static FORCE_LINK: &str = "force-link";
+static REVOKE_SESSION: &str = "revoke-session";
+static RESET_CODE: &str = "reset-code";
+static LIST_PENDING: &str = "list-pending";
+static USER: &str = "user";
+static MINECRAFT_NAME: &str = "minecraft-name";
+static SERVER: &str = "server";
+static RECENT_REQUESTS: i64 = 10;
+static MAX_LISTED_REGISTRATIONS: usize = 50;
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("admin.unknown-minecraft-name", &["minecraft_user"]),
+    ("admin.not-registered", &["discord_id"]),
+    ("admin.not-locked", &["discord_id"]),
+    ("admin.unlocked", &["discord_id"]),
+    ("admin.unregistered", &["discord_id", "session_notice"]),
+    ("admin.not-logged-in", &["discord_id"]),
+    ("admin.session-revoked", &["discord_id"]),
+    ("admin.code-reset", &["discord_id"]),
+    ("admin.code-reset-undelivered", &["discord_id"]),
+    ("admin.code-reset-linked", &["discord_id"]),
+    ("admin.already-linked", &["discord_id", "minecraft_user"]),
+    ("admin.name-taken", &["discord_id", "minecraft_user"]),
+    ("admin.linked", &["discord_id", "minecraft_user"]),
+    ("admin.pending", &["count", "registrations"]),
+    ("admin.more-pending", &["count"]),
+    ("admin.session-active", &["expiration", "ip_address"]),
+    ("admin.request", &["created", "ip_address", "minecraft_server", "state"]),
+];
+
+pub struct AdminCommand {
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    settings: RegistrationSettings,
+    code_generator: Box<dyn CodeGenerator>,
+    catalog: Arc<Catalog>,
+}
+
+#[async_trait]
+impl SlashCommand for AdminCommand {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        DESCRIPTION.to_string()
+    }
+
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    fn default_member_permissions(&self) -> Option<Permissions> {
+        Some(Permissions::MODERATE_MEMBERS)
+    }
+
+    fn subcommands(&self) -> Vec<SubCommand> {
+        vec![
+            SubCommand {
+                name: LOOKUP,
+                description: "Look up a player by Discord user or Minecraft name",
+                options: vec![user_option(false), minecraft_name_option(false, true)],
+            },
+            SubCommand {
+                name: INVESTIGATE,
+                description: "Show the account state and recent login requests of a user",
+                options: vec![
+                    user_option(true),
+                    CommandOption {
+                        name: SERVER,
+                        description: "Only show login requests for this Minecraft server",
+                        kind: OptionKind::String,
+                        required: false,
+                        choices: Vec::new(),
+                        autocomplete: true,
+                    },
+                ],
+            },
+            SubCommand {
+                name: UNLOCK,
+                description: "Unlock the Minecraft account of a user",
+                options: vec![user_option(true)],
+            },
+            SubCommand {
+                name: FORCE_UNREGISTER,
+                description: "Remove the registration of a user",
+                options: vec![user_option(true)],
+            },
+            SubCommand {
+                name: FORCE_LINK,
+                description: "Link a Minecraft name to a user without a registration code",
+                options: vec![user_option(true), minecraft_name_option(true, false)],
+            },
+            SubCommand {
+                name: REVOKE_SESSION,
+                description: "End the current Minecraft session of a user",
+                options: vec![user_option(true)],
+            },
+            SubCommand {
+                name: RESET_CODE,
+                description: "Send a user who has not linked their Minecraft account yet a new registration code",
+                options: vec![user_option(true)],
+            },
+            SubCommand {
+                name: LIST_PENDING,
+                description: "List the registrations that have not been completed in Minecraft",
+                options: Vec::new(),
+            },
+        ]
+    }
+
+    async fn run(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+        arguments: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
+        Ok(self.run_subcommand(ctx, command, arguments).await?.into())
+    }
+
+    async fn autocomplete(&self, option: &str, value: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+        let limit = MAX_AUTOCOMPLETE_CHOICES as i64;
+
+        match option {
+            name if name == MINECRAFT_NAME => Ok(database.get_minecraft_users_starting_with(value, &limit).await?),
+            name if name == SERVER => Ok(database.get_minecraft_servers_starting_with(value, &limit).await?),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn user_option(required: bool) -> CommandOption {
+    CommandOption {
+        name: USER,
+        description: "The Discord user",
+        kind: OptionKind::User,
+        required,
+        choices: Vec::new(),
+        autocomplete: false,
+    }
+}
+
+fn minecraft_name_option(required: bool, autocomplete: bool) -> CommandOption {
+    CommandOption {
+        name: MINECRAFT_NAME,
+        description: "The Minecraft user name",
+        kind: OptionKind::String,
+        required,
+        choices: Vec::new(),
+        autocomplete,
+    }
+}
+
+fn admin_embed(catalog: &Catalog, locale: &str, description: String, colour: Colour) -> EmbedData {
+    EmbedData {
+        title: Some(catalog.message(locale, "admin.title", &[])),
+        description: Some(description),
+        colour: Some(colour),
+        ..Default::default()
+    }
+}
+
+async fn force_link(
+    database: &Database,
+    catalog: &Catalog,
+    locale: &str,
+    discord_id: &str,
+    arguments: &CommandArguments,
+) -> Result<EmbedData, Box<dyn Error>> {
+    let minecraft_user = match arguments.get_string(MINECRAFT_NAME) {
+        Some(minecraft_user) => minecraft_user,
+        None => return Err("Missing Minecraft name".into()),
+    };
+
+    match database.find_discord_id(minecraft_user).await? {
+        Some(linked_id) if linked_id == discord_id => {
+            let description = catalog.message(
+                locale,
+                "admin.already-linked",
+                &[("discord_id", discord_id), ("minecraft_user", minecraft_user)],
+            );
+            return Ok(admin_embed(catalog, locale, description, Colour::ORANGE));
+        }
+        Some(linked_id) => {
+            let description = catalog.message(
+                locale,
+                "admin.name-taken",
+                &[("discord_id", &linked_id), ("minecraft_user", minecraft_user)],
+            );
+            return Ok(admin_embed(catalog, locale, description, Colour::RED));
+        }
+        None => (),
+    }
+
+    if let Err(DatabaseError::PlayerNotRegistered(_)) = database.is_player_registered(discord_id).await {
+        // The user never sees this code, so it is expired right away
+        store_unique_code(&LongCodeGenerator::default(), |reg_code| async move {
+            database.add_player(discord_id, &reg_code, &0).await
+        })
+        .await?;
+    }
+
+    database.revoke_player_session(discord_id).await?;
+    database.set_minecraft_user(discord_id, minecraft_user).await?;
+
+    let description = catalog.message(
+        locale,
+        "admin.linked",
+        &[("discord_id", discord_id), ("minecraft_user", minecraft_user)],
+    );
+    Ok(admin_embed(catalog, locale, description, Colour::DARK_GREEN))
+}
+
+// Replaces the code of a registration that has not been completed and sends the new code to the user
+async fn reset_code(
+    ctx: &Context,
+    database: &Database,
+    catalog: &Catalog,
+    locale: &str,
+    discord_id: &str,
+    code_generator: &dyn CodeGenerator,
+    code_ttl_minutes: i32,
+) -> Result<EmbedData, Box<dyn Error>> {
+    let user = &[("discord_id", discord_id)];
+
+    match database.get_minecraft_user(discord_id).await {
+        Ok(_) => {
+            let description = catalog.message(locale, "admin.code-reset-linked", user);
+            return Ok(admin_embed(catalog, locale, description, Colour::ORANGE));
+        }
+        Err(DatabaseError::MissingMinecraftId(_)) => (),
+        Err(e) => return Err(Box::new(e)),
+    }
+
+    let reg_code = store_unique_code(code_generator, |reg_code| async move {
+        database.set_reg_code(discord_id, &reg_code, &code_ttl_minutes).await
+    })
+    .await?;
+    let expiration = database.get_reg_code_expiration(discord_id).await?;
+
+    // The DM is in the language of the user rather than the one of the moderator
+    let user_locale = database
+        .get_locale(discord_id)
+        .await?
+        .unwrap_or_else(|| catalog.default_language().to_string());
+    let instructions: ResponseData =
+        registration_instructions(catalog, &user_locale, "register.reset", &reg_code, expiration).into();
+    let delivered = match UserId(discord_id.parse()?).create_dm_channel(ctx).await {
+        Ok(channel) => channel
+            .send_message(ctx, |m| m.set_embeds(instructions.create_embeds()))
+            .await
+            .map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = delivered {
+        println!("Could not send the new registration code to the Discord user {}: {}", discord_id, e);
+        let description = catalog.message(locale, "admin.code-reset-undelivered", user);
+        return Ok(admin_embed(catalog, locale, description, Colour::ORANGE));
+    }
+
+    let description = catalog.message(locale, "admin.code-reset", user);
+    Ok(admin_embed(catalog, locale, description, Colour::DARK_GREEN))
+}
+
+async fn list_pending(database: &Database, catalog: &Catalog, locale: &str) -> Result<EmbedData, Box<dyn Error>> {
+    let discord_ids = database.get_pending_registrations().await?;
+
+    if discord_ids.is_empty() {
+        return Ok(admin_embed(catalog, locale, catalog.message(locale, "admin.no-pending", &[]), Colour::DARK_GREEN));
+    }
+
+    let mut registrations = discord_ids
+        .iter()
+        .take(MAX_LISTED_REGISTRATIONS)
+        .map(|discord_id| format!("<@{}>", discord_id))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    if discord_ids.len() > MAX_LISTED_REGISTRATIONS {
+        let more = (discord_ids.len() - MAX_LISTED_REGISTRATIONS).to_string();
+        registrations = format!("{}\n\n{}", registrations, catalog.message(locale, "admin.more-pending", &[("count", &more)]));
+    }
+
+    let description = catalog.message(
+        locale,
+        "admin.pending",
+        &[("count", &discord_ids.len().to_string()), ("registrations", &registrations)],
+    );
+    Ok(admin_embed(catalog, locale, description, Colour::ORANGE))
+}
+
+async fn lookup(
+    database: &Database,
+    catalog: &Catalog,
+    locale: &str,
+    discord_id: &str,
+    locked: bool,
+    with_history: bool,
+    minecraft_server: Option<&str>,
+) -> Result<EmbedData, Box<dyn Error>> {
+    let minecraft_user = match database.get_minecraft_user(discord_id).await {
+        Ok(minecraft_user) => Some(minecraft_user),
+        Err(DatabaseError::MissingMinecraftId(_)) => None,
+        Err(e) => return Err(Box::new(e)),
+    };
+
+    let none = catalog.message(locale, "common.none", &[]);
+    let session = match database.get_player_session(discord_id).await? {
+        Some(session) => catalog.message(
+            locale,
+            "admin.session-active",
+            &[
+                ("expiration", &session.expiration.to_string()),
+                (
+                    "ip_address",
+                    &session.ip_address.unwrap_or_else(|| catalog.message(locale, "common.unknown", &[])),
+                ),
+            ],
+        ),
+        None => none.clone(),
+    };
+
+    let mut embed = EmbedData {
+        title: Some(catalog.message(locale, "admin.title", &[])),
+        colour: Some(if locked { Colour::RED } else { Colour::DARK_GREEN }),
+        thumbnail: minecraft_user.as_deref().map(minecraft_avatar_url),
+        ..Default::default()
+    }
+    .field(catalog.message(locale, "admin.discord-user", &[]), format!("<@{}>", discord_id), true)
+    .field(
+        catalog.message(locale, "admin.minecraft-name", &[]),
+        minecraft_user.clone().unwrap_or_else(|| catalog.message(locale, "admin.not-linked", &[])),
+        true,
+    )
+    .field(
+        catalog.message(locale, "admin.locked", &[]),
+        catalog.message(locale, if locked { "common.yes" } else { "common.no" }, &[]),
+        true,
+    )
+    .field(catalog.message(locale, "admin.session", &[]), session, false);
+
+    if with_history {
+        let trusted_addresses = database.get_trusted_addresses(discord_id).await?;
+        let trusted_addresses = if trusted_addresses.is_empty() {
+            none.clone()
+        } else {
+            trusted_addresses
+                .iter()
+                .map(|a| format!("`{}`", a.ip_address))
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        let requests = match &minecraft_user {
+            Some(minecraft_user) => database
+                .get_recent_authentication_requests(minecraft_user, minecraft_server, &RECENT_REQUESTS)
+                .await?,
+            None => Vec::new(),
+        };
+        let requests = if requests.is_empty() {
+            none
+        } else {
+            requests
+                .iter()
+                .map(|r| {
+                    catalog.message(
+                        locale,
+                        "admin.request",
+                        &[
+                            ("created", &r.created.to_string()),
+                            ("ip_address", &r.ip_address),
+                            ("minecraft_server", &r.minecraft_server),
+                            ("state", &describe_state(catalog, locale, &r.state)),
+                        ],
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        embed = embed
+            .field(catalog.message(locale, "admin.trusted-networks", &[]), trusted_addresses, false)
+            .field(catalog.message(locale, "admin.recent-requests", &[]), requests, false);
+    }
+
+    Ok(embed)
+}
+
+impl AdminCommand {
+    async fn run_subcommand(
+        &self,
+        ctx: &Context,
+        command: &ApplicationCommandInteraction,
+        arguments: &CommandArguments,
+    ) -> Result<EmbedData, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+        let locale = &user_locale(&database, command).await?;
+        let catalog = &self.catalog;
+
+        let subcommand = match arguments.subcommand() {
+            Some(subcommand) => subcommand,
+            None => return Err("Missing subcommand".into()),
+        };
+
+        if subcommand == LIST_PENDING {
+            return list_pending(&database, catalog, locale).await;
+        }
+
+        let discord_id = match arguments.get_user(USER) {
+            Some(discord_id) => discord_id.to_string(),
+            None if subcommand == LOOKUP => {
+                let minecraft_user = match arguments.get_string(MINECRAFT_NAME) {
+                    Some(minecraft_user) => minecraft_user,
+                    None => {
+                        let description = catalog.message(locale, "admin.lookup-missing", &[]);
+                        return Ok(admin_embed(catalog, locale, description, Colour::RED));
+                    }
+                };
+
+                match database.find_discord_id(minecraft_user).await? {
+                    Some(discord_id) => discord_id,
+                    None => {
+                        let description = catalog.message(
+                            locale,
+                            "admin.unknown-minecraft-name",
+                            &[("minecraft_user", minecraft_user)],
+                        );
+                        return Ok(admin_embed(catalog, locale, description, Colour::RED));
+                    }
+                }
+            }
+            None => return Err("Missing user".into()),
+        };
+        let user = &[("discord_id", discord_id.as_str())];
+
+        if subcommand == FORCE_LINK {
+            return force_link(&database, catalog, locale, &discord_id, arguments).await;
+        }
+
+        let locked = match database.is_player_locked(&discord_id).await {
+            Ok(locked) => locked,
+            Err(DatabaseError::PlayerNotRegistered(_)) => {
+                let description = catalog.message(locale, "admin.not-registered", user);
+                return Ok(admin_embed(catalog, locale, description, Colour::RED));
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        match subcommand {
+            name if name == LOOKUP => lookup(&database, catalog, locale, &discord_id, locked, false, None).await,
+            name if name == INVESTIGATE => {
+                let minecraft_server = arguments.get_string(SERVER);
+                lookup(&database, catalog, locale, &discord_id, locked, true, minecraft_server).await
+            }
+            name if name == UNLOCK => {
+                if !locked {
+                    let description = catalog.message(locale, "admin.not-locked", user);
+                    return Ok(admin_embed(catalog, locale, description, Colour::ORANGE));
+                }
+
+                database.set_player_locked(&discord_id, &false).await?;
+                Ok(admin_embed(catalog, locale, catalog.message(locale, "admin.unlocked", user), Colour::DARK_GREEN))
+            }
+            name if name == FORCE_UNREGISTER => {
+                let revoked_session = database.revoke_player_session(&discord_id).await?.is_some();
+                database.delete_player(&discord_id).await?;
+
+                let session_notice = if revoked_session {
+                    catalog.message(locale, "admin.session-notice", &[])
+                } else {
+                    String::new()
+                };
+                let description = catalog.message(
+                    locale,
+                    "admin.unregistered",
+                    &[("discord_id", &discord_id), ("session_notice", &session_notice)],
+                );
+                Ok(admin_embed(catalog, locale, description, Colour::DARK_GREEN))
+            }
+            name if name == REVOKE_SESSION => {
+                if database.revoke_player_session(&discord_id).await?.is_none() {
+                    let description = catalog.message(locale, "admin.not-logged-in", user);
+                    return Ok(admin_embed(catalog, locale, description, Colour::ORANGE));
+                }
+
+                let description = catalog.message(locale, "admin.session-revoked", user);
+                Ok(admin_embed(catalog, locale, description, Colour::DARK_GREEN))
+            }
+            name if name == RESET_CODE => {
+                let code_ttl_minutes = self.settings.code_ttl_minutes as i32;
+                reset_code(ctx, &database, catalog, locale, &discord_id, self.code_generator.as_ref(), code_ttl_minutes).await
+            }
+            _ => Err(format!("Unknown subcommand '{}'", subcommand).into()),
+        }
+    }
+
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        settings: RegistrationSettings,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        let pool = Arc::clone(&db_connection_pool);
+        AdminCommand {
+            db_connection_pool: pool,
+            code_generator: settings.code_generator(),
+            settings,
+            catalog,
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use crate::services::database::Database;
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::Catalog;
+
+use super::super::arguments::{CommandArguments, OptionValue};
+use super::super::command::{CommandOption, OptionChoice, OptionKind, SlashCommand};
+use async_trait::async_trait;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::Context;
+use serenity::utils::Colour;
+
+use std::error::Error;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+static NAME: &str = "language";
+static DESCRIPTION: &str = "Choose the language I use when talking to you";
+static LANGUAGE: &str = "language";
+// Removes the stored language so the language of the Discord client is used
+static AUTOMATIC: &str = "auto";
+
+pub struct LanguageCommand {
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    catalog: Arc<Catalog>,
+}
+
+#[async_trait]
+impl SlashCommand for LanguageCommand {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        DESCRIPTION.to_string()
+    }
+
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    fn options(&self) -> Vec<CommandOption> {
+        // Every language is listed by its own name
+        let mut choices: Vec<OptionChoice> = self
+            .catalog
+            .languages()
+            .into_iter()
+            .map(|language| OptionChoice {
+                name: self.catalog.message(language, "language.name", &[]),
+                value: OptionValue::String(language.to_string()),
+            })
+            .collect();
+        choices.push(OptionChoice {
+            name: self.catalog.message(self.catalog.default_language(), "language.automatic-choice", &[]),
+            value: OptionValue::String(AUTOMATIC.to_string()),
+        });
+
+        vec![CommandOption {
+            name: LANGUAGE,
+            description: "The language you want me to use",
+            kind: OptionKind::String,
+            required: true,
+            choices,
+            autocomplete: false,
+        }]
+    }
+
+    async fn run(
+        &self,
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        arguments: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+
+        let language = match arguments.get_string(LANGUAGE) {
+            Some(language) => language,
+            None => return Err("Missing language".into()),
+        };
+        let (locale, message) = if language == AUTOMATIC {
+            database.set_locale(&command.user.id.to_string(), None).await?;
+            (command.locale.as_str(), "language.automatic")
+        } else {
+            database.set_locale(&command.user.id.to_string(), Some(language)).await?;
+            (language, "language.set")
+        };
+
+        Ok(EmbedData {
+            title: Some(self.catalog.message(locale, "language.title", &[])),
+            description: Some(self.catalog.message(locale, message, &[])),
+            colour: Some(Colour::DARK_GREEN),
+            ..Default::default()
+        }
+        .into())
+    }
+}
+
+impl LanguageCommand {
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        let pool = Arc::clone(&db_connection_pool);
+        LanguageCommand {
+            db_connection_pool: pool,
+            catalog,
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use crate::services::database::Database;
+use crate::services::database::DatabaseError;
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::{Catalog, MessageParameters};
+
+use super::super::arguments::CommandArguments;
+use super::super::command::SlashCommand;
+use super::user_locale;
+use async_trait::async_trait;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::Context;
+use serenity::utils::Colour;
+
+use std::error::Error;
+use std::time::Duration;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+static NAME: &str = "logout";
+static DESCRIPTION: &str = "End your current Minecraft session";
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("logout.success", &["remaining"]),
+];
+
+pub struct LogoutCommand {
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    catalog: Arc<Catalog>,
+}
+
+#[async_trait]
+impl SlashCommand for LogoutCommand {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        DESCRIPTION.to_string()
+    }
+
+    async fn run(
+        &self,
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        _: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+
+        let discord_id = command.user.id.to_string();
+        let locale = &user_locale(&database, command).await?;
+        let catalog = &self.catalog;
+
+        match database.is_player_registered(&discord_id).await {
+            Ok(_) => (),
+            Err(DatabaseError::PlayerNotRegistered(_)) => {
+                return Ok(EmbedData {
+                    title: Some(catalog.message(locale, "logout.title", &[])),
+                    description: Some(catalog.message(locale, "logout.not-registered", &[])),
+                    colour: Some(Colour::RED),
+                    ..Default::default()
+                }.into())
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        let remaining_seconds = match database.revoke_player_session(&discord_id).await? {
+            Some(remaining_seconds) => remaining_seconds,
+            None => {
+                return Ok(EmbedData {
+                    title: Some(catalog.message(locale, "logout.title", &[])),
+                    description: Some(catalog.message(locale, "logout.not-logged-in", &[])),
+                    colour: Some(Colour::RED),
+                    ..Default::default()
+                }.into())
+            }
+        };
+
+        Ok(EmbedData {
+            title: Some(catalog.message(locale, "logout.title", &[])),
+            description: Some(catalog.message(
+                locale,
+                "logout.success",
+                &[("remaining", &format_remaining_time(catalog, locale, remaining_seconds))],
+            )),
+            colour: Some(Colour::DARK_GREEN),
+            ..Default::default()
+        }.into())
+    }
+}
+
+// Rounded down to whole minutes
+fn format_remaining_time(catalog: &Catalog, locale: &str, seconds: i64) -> String {
+    match seconds / 60 {
+        0 => catalog.message(locale, "logout.less-than-a-minute", &[]),
+        minutes => catalog.duration(locale, Duration::from_secs(minutes as u64 * 60)),
+    }
+}
+
+impl LogoutCommand {
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        let pool = Arc::clone(&db_connection_pool);
+        LogoutCommand {
+            db_connection_pool: pool,
+            catalog,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::commands::test_interaction;
+    use crate::services::database::test_database::TestDatabase;
+    use crate::services::localization::test_catalog;
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn expired_sessions_are_not_logged_in() {
+        let database = TestDatabase::new().await;
+        let db = Database::new(Arc::clone(&database.pool)).await.unwrap();
+        db.add_player("1001", "CODE", &15).await.unwrap();
+        db.set_minecraft_user("1001", "Steve").await.unwrap();
+        let request_id = db.add_authentication_request("Steve", "127.0.0.1", "lobby").await.unwrap();
+        db.add_player_auth("1001", &request_id, &-1).await.unwrap();
+
+        let catalog = Arc::new(test_catalog::catalog());
+        let command = LogoutCommand::new(Arc::clone(&database.pool), Arc::clone(&catalog));
+        let response = command
+            .run(&test_interaction::context(), &test_interaction::command(NAME, 1001), &CommandArguments::default())
+            .await
+            .unwrap();
+        database.drop().await;
+
+        assert_eq!(
+            response.embeds[0].description,
+            Some(catalog.message("en", "logout.not-logged-in", &[]))
+        );
+    }
+}
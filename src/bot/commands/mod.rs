@@ -1,31 +1,141 @@
 use std::sync::Arc;
 
 use super::command::SlashCommand;
+use super::commands::admin::AdminCommand;
+use super::commands::language::LanguageCommand;
+use super::commands::logout::LogoutCommand;
 use super::commands::pong::PongCommand;
 use super::commands::register::RegisterCommand;
+use super::commands::status::StatusCommand;
+use super::commands::trusted::TrustedCommand;
+use super::commands::unlock::UnlockCommand;
 use super::commands::unregister::UnregisterCommand;
 
+use crate::services::database::{Database, DatabaseError};
+use crate::services::localization::{Catalog, MessageParameters};
+use crate::services::settings::Settings;
+
 use bb8::Pool;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use bb8_postgres::PostgresConnectionManager;
 use tokio_postgres::NoTls;
 
+mod admin;
+mod language;
+mod logout;
 mod pong;
 mod register;
+mod status;
+mod trusted;
+mod unlock;
 mod unregister;
 
-pub fn get_commands<'a>(
+pub fn get_commands(
     db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    settings: &Settings,
+    catalog: Arc<Catalog>,
 ) -> Vec<Arc<Box<dyn SlashCommand + 'static>>> {
     let register_pool = Arc::clone(&db_connection_pool);
     let unregister_pool = Arc::clone(&db_connection_pool);
-    let pong: Arc<Box<dyn SlashCommand + 'static>> = Arc::new(PongCommand::new());
+    let logout_pool = Arc::clone(&db_connection_pool);
+    let status_pool = Arc::clone(&db_connection_pool);
+    let trusted_pool = Arc::clone(&db_connection_pool);
+    let unlock_pool = Arc::clone(&db_connection_pool);
+    let admin_pool = Arc::clone(&db_connection_pool);
+    let language_pool = Arc::clone(&db_connection_pool);
+    let pong: Arc<Box<dyn SlashCommand + 'static>> = Arc::new(Box::new(PongCommand::new(Arc::clone(&catalog))));
     let register: Arc<Box<dyn SlashCommand + 'static>> =
-        Arc::new(RegisterCommand::new(register_pool));
+        Arc::new(Box::new(RegisterCommand::new(register_pool, settings.registration.clone(), Arc::clone(&catalog))));
     let unregister: Arc<Box<dyn SlashCommand + 'static>> =
-        Arc::new(UnregisterCommand::new(unregister_pool));
-    let mut commands = Vec::new();
-    commands.push(pong);
-    commands.push(register);
-    commands.push(unregister);
+        Arc::new(Box::new(UnregisterCommand::new(unregister_pool, Arc::clone(&catalog))));
+    let logout: Arc<Box<dyn SlashCommand + 'static>> =
+        Arc::new(Box::new(LogoutCommand::new(logout_pool, Arc::clone(&catalog))));
+    let status: Arc<Box<dyn SlashCommand + 'static>> =
+        Arc::new(Box::new(StatusCommand::new(status_pool, Arc::clone(&catalog))));
+    let trusted: Arc<Box<dyn SlashCommand + 'static>> =
+        Arc::new(Box::new(TrustedCommand::new(trusted_pool, Arc::clone(&catalog))));
+    let unlock: Arc<Box<dyn SlashCommand + 'static>> =
+        Arc::new(Box::new(UnlockCommand::new(unlock_pool, Arc::clone(&catalog))));
+    let admin: Arc<Box<dyn SlashCommand + 'static>> =
+        Arc::new(Box::new(AdminCommand::new(admin_pool, settings.registration.clone(), Arc::clone(&catalog))));
+    let language: Arc<Box<dyn SlashCommand + 'static>> =
+        Arc::new(Box::new(LanguageCommand::new(language_pool, catalog)));
+    let mut commands = vec![pong, register, unregister, logout, status, unlock, language];
+
+    if settings.features.trusted_networks {
+        commands.push(trusted);
+    }
+
+    if settings.features.admin_commands {
+        commands.push(admin);
+    }
+
     commands
 }
+
+pub fn message_parameters() -> Vec<MessageParameters> {
+    [
+        admin::MESSAGE_PARAMETERS,
+        logout::MESSAGE_PARAMETERS,
+        register::MESSAGE_PARAMETERS,
+        status::MESSAGE_PARAMETERS,
+        trusted::MESSAGE_PARAMETERS,
+    ]
+    .concat()
+}
+
+// The language chosen with /language, otherwise the language of the user's Discord client
+async fn user_locale(
+    database: &Database,
+    command: &ApplicationCommandInteraction,
+) -> Result<String, DatabaseError> {
+    Ok(database
+        .get_locale(&command.user.id.to_string())
+        .await?
+        .unwrap_or_else(|| command.locale.clone()))
+}
+
+#[cfg(test)]
+pub mod test_interaction {
+    use std::sync::Arc;
+
+    use futures::channel::mpsc;
+    use serenity::cache::Cache;
+    use serenity::client::bridge::gateway::ShardMessenger;
+    use serenity::http::Http;
+    use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+    use serenity::prelude::{Context, RwLock, TypeMap};
+
+    pub fn context() -> Context {
+        let (tx, _) = mpsc::unbounded();
+        Context {
+            data: Arc::new(RwLock::new(TypeMap::new())),
+            shard: ShardMessenger::new(tx),
+            shard_id: 0,
+            http: Arc::new(Http::new("")),
+            cache: Arc::new(Cache::new()),
+        }
+    }
+
+    // A slash command interaction as Discord would send it when `user_id` invokes `name` in a guild
+    pub fn command(name: &str, user_id: u64) -> ApplicationCommandInteraction {
+        serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "application_id": "2",
+            "type": 2,
+            "data": { "id": "3", "name": name, "type": 1 },
+            "guild_id": "4",
+            "channel_id": "5",
+            "user": {
+                "id": user_id.to_string(),
+                "username": format!("user{}", user_id),
+                "discriminator": "0001",
+                "avatar": null
+            },
+            "token": "token",
+            "version": 1,
+            "locale": "en-US"
+        }))
+        .unwrap()
+    }
+}
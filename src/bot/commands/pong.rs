@@ -1,9 +1,14 @@
-use crate::services::embed::EmbedData;
+use std::sync::Arc;
 
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::Catalog;
+
+use super::super::arguments::CommandArguments;
 use super::super::command::SlashCommand;
 use async_trait::async_trait;
 use serenity::{
-    model::prelude::interaction::application_command::CommandDataOption, prelude::Context,
+    model::prelude::interaction::application_command::ApplicationCommandInteraction,
+    prelude::Context,
     utils::Colour,
 };
 use std::error::Error;
@@ -11,7 +16,9 @@ use std::error::Error;
 static NAME: &str = "ping";
 static DESCRIPTION: &str = "A ping command";
 
-pub struct PongCommand;
+pub struct PongCommand {
+    catalog: Arc<Catalog>,
+}
 
 #[async_trait]
 impl SlashCommand for PongCommand {
@@ -23,17 +30,18 @@ impl SlashCommand for PongCommand {
         DESCRIPTION.to_string()
     }
 
-    async fn run(&self, _: &Context, _: &[CommandDataOption]) -> Result<EmbedData, Box<dyn Error>> {
+    async fn run(&self, _: &Context, command: &ApplicationCommandInteraction, _: &CommandArguments) -> Result<ResponseData, Box<dyn Error>> {
         Ok(EmbedData {
-            title: Some("Ping pong".to_string()),
-            description: Some("Pong!".to_string()),
+            title: Some(self.catalog.message(&command.locale, "ping.title", &[])),
+            description: Some(self.catalog.message(&command.locale, "ping.pong", &[])),
             colour: Some(Colour::DARK_GREEN),
-        })
+            ..Default::default()
+        }.into())
     }
 }
 
 impl<'a> PongCommand {
-    pub fn new() -> Box<dyn SlashCommand + 'static> {
-        Box::new(PongCommand {})
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        PongCommand { catalog }
     }
 }
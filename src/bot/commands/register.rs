@@ -2,16 +2,21 @@ use std::sync::Arc;
 
 use crate::services::database::Database;
 use crate::services::database::DatabaseError;
-use crate::services::embed::EmbedData;
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::{Catalog, MessageParameters};
+use crate::services::registration_code::{store_unique_code, CodeGenerator};
+use crate::services::settings::RegistrationSettings;
 
-use super::super::command::SlashCommand;
+use super::super::arguments::CommandArguments;
+use super::super::command::{SlashCommand, SubCommand};
+use super::user_locale;
 use async_trait::async_trait;
-use rand::{distributions::Alphanumeric, Rng};
-use serenity::model::prelude::interaction::application_command::CommandDataOption;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::prelude::Context;
 use serenity::utils::Colour;
 
 use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
@@ -19,9 +24,21 @@ use tokio_postgres::NoTls;
 
 static NAME: &str = "register";
 static DESCRIPTION: &str = "Register yourself to the Minecraft server";
+static START: &str = "start";
+static REGENERATE: &str = "regenerate";
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("register.instructions", &["intro"]),
+    ("register.command-text", &["code"]),
+    ("register.expires-at", &["expiration"]),
+    ("register.pending", &["code_status"]),
+    ("register.code-expires", &["expiration"]),
+];
 
 pub struct RegisterCommand {
     db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    settings: RegistrationSettings,
+    code_generator: Box<dyn CodeGenerator>,
+    catalog: Arc<Catalog>,
 }
 
 #[async_trait]
@@ -34,66 +51,243 @@ impl SlashCommand for RegisterCommand {
         DESCRIPTION.to_string()
     }
 
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    fn subcommands(&self) -> Vec<SubCommand> {
+        vec![
+            SubCommand {
+                name: START,
+                description: "Get a code to link your Minecraft account to your Discord account",
+                options: Vec::new(),
+            },
+            SubCommand {
+                name: REGENERATE,
+                description: "Replace your registration code with a new one",
+                options: Vec::new(),
+            },
+        ]
+    }
+
     async fn run(
         &self,
-        ctx: &Context,
-        _: &[CommandDataOption],
-    ) -> Result<EmbedData, Box<dyn Error>> {
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        arguments: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
         let pool = Arc::clone(&self.db_connection_pool);
-        let database = Database::new(pool).await?;
-
-        let discord_user = ctx.http.get_current_user().await?;
-        let is_player_registered = database
-            .is_player_registered(&discord_user.id.to_string())
-            .await;
-        match is_player_registered { 
-            Ok(_) => {
-                match database.get_minecraft_user(&discord_user.id.to_string()).await {
-                    Ok(_) => return Ok(EmbedData {
-                        title: Some("Minecraft registration".to_string()),
-                        description: Some("You are already registered on the Minecraft server. Please unregister before trying to register again.".to_string()),
-                        colour: Some(Colour::RED),
-                    }),
-                    Err(DatabaseError::MissingMinecraftId(_)) => {
-                        let result = database.get_reg_code(&discord_user.id.to_string()).await;
-                        match result {
-                            Ok(reg_code) => {
-                                return Ok(EmbedData{
-                                    title: Some("Minecraft registration".to_string()),
-                                    description: Some(format!("You have a pending registration status. To complete the registration process, please open Minecraft, click on Multiplayer and join the server\n\n```\nminecraft.wahlberger.dev\n```\n\nOnce joined, enter the following command in minecraft:\n\n```\n/register {}\n```\n\nto link your Minecraft account to your Discord account.", reg_code).to_string()),
-                                    colour: Some(Colour::RED),
-                                })
-                            },
-                            Err(e) => return Err(Box::new(e))
-                        }
-                    },
-                    Err(e) => return Err(Box::new(e)),
-                }
-            },
-            Err(DatabaseError::PlayerNotRegistered(_)) => {
-                let reg_code: String = rand::thread_rng()
-                    .sample_iter(&Alphanumeric)
-                    .take(32)
-                    .map(char::from)
-                    .collect();
+        let database = &Database::new(pool).await?;
+
+        let discord_id = &command.user.id.to_string();
+        let locale = &user_locale(database, command).await?;
+        let catalog = &self.catalog;
+        let code_ttl_minutes = self.settings.code_ttl_minutes as i32;
+        let regenerate = arguments.subcommand() == Some(REGENERATE);
 
-                database.add_player(&discord_user.id.to_string(), &reg_code).await?;
+        match database.is_player_registered(discord_id).await {
+            Ok(_) => (),
+            Err(DatabaseError::PlayerNotRegistered(_)) if regenerate => {
                 return Ok(EmbedData {
-                        title: Some("Minecraft registration".to_string()),
-                        description: Some(format!("There we go! I have added a registration request for you! To complete the registration process, please open Minecraft, click on Multiplayer and join the server\n\n```\nminecraft.wahlberger.dev\n```\n\nOnce joined, enter the following command in minecraft:\n\n```\n/register {}\n```\n\nto link your Minecraft account to your Discord account.", reg_code)),
-                        colour: Some(Colour::DARK_GREEN),
-                    })
-            },
-            Err(e) => Err(Box::new(e))
+                    title: Some(catalog.message(locale, "register.title", &[])),
+                    description: Some(catalog.message(locale, "register.not-registered", &[])),
+                    colour: Some(Colour::RED),
+                    ..Default::default()
+                }.into())
+            }
+            Err(DatabaseError::PlayerNotRegistered(_)) => {
+                let reg_code = store_unique_code(self.code_generator.as_ref(), |reg_code| async move {
+                    database.add_player(discord_id, &reg_code, &code_ttl_minutes).await
+                })
+                .await?;
+                let expiration = database.get_reg_code_expiration(discord_id).await?;
+
+                return Ok(registration_instructions(catalog, locale, "register.created", &reg_code, expiration).into());
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        match database.get_minecraft_user(discord_id).await {
+            Ok(_) => return Ok(EmbedData {
+                title: Some(catalog.message(locale, "register.title", &[])),
+                description: Some(catalog.message(locale, "register.already-registered", &[])),
+                colour: Some(Colour::RED),
+                ..Default::default()
+            }.into()),
+            Err(DatabaseError::MissingMinecraftId(_)) => (),
+            Err(e) => return Err(Box::new(e)),
         }
+
+        if regenerate {
+            let reg_code = store_unique_code(self.code_generator.as_ref(), |reg_code| async move {
+                database.set_reg_code(discord_id, &reg_code, &code_ttl_minutes).await
+            })
+            .await?;
+            let expiration = database.get_reg_code_expiration(discord_id).await?;
+
+            return Ok(registration_instructions(catalog, locale, "register.regenerated", &reg_code, expiration).into());
+        }
+
+        let expiration = database.get_reg_code_expiration(discord_id).await?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let code_status = if expiration > now {
+            catalog.message(locale, "register.code-expires", &[("expiration", &expiration.to_string())])
+        } else {
+            catalog.message(locale, "register.code-expired", &[])
+        };
+
+        Ok(EmbedData {
+            title: Some(catalog.message(locale, "register.title", &[])),
+            description: Some(catalog.message(locale, "register.pending", &[("code_status", &code_status)])),
+            colour: Some(Colour::RED),
+            ..Default::default()
+        }.into())
+    }
+}
+
+pub(super) fn registration_instructions(catalog: &Catalog, locale: &str, intro: &str, reg_code: &str, expiration: i64) -> EmbedData {
+    let intro = catalog.message(locale, intro, &[]);
+
+    EmbedData {
+        title: Some(catalog.message(locale, "register.title", &[])),
+        description: Some(catalog.message(locale, "register.instructions", &[("intro", &intro)])),
+        colour: Some(Colour::DARK_GREEN),
+        footer: Some(catalog.message(locale, "register.footer", &[])),
+        ..Default::default()
     }
+    .field(
+        catalog.message(locale, "register.server", &[]),
+        catalog.message(locale, "register.server-address", &[]),
+        false,
+    )
+    .field(
+        catalog.message(locale, "register.command", &[]),
+        catalog.message(locale, "register.command-text", &[("code", reg_code)]),
+        false,
+    )
+    .field(
+        catalog.message(locale, "register.expires", &[]),
+        catalog.message(locale, "register.expires-at", &[("expiration", &expiration.to_string())]),
+        false,
+    )
 }
 
 impl RegisterCommand {
-    pub fn new(db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>) -> Box<dyn SlashCommand + 'static> {
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        settings: RegistrationSettings,
+        catalog: Arc<Catalog>,
+    ) -> Self {
         let pool = Arc::clone(&db_connection_pool);
-        Box::new(RegisterCommand {
+        RegisterCommand {
             db_connection_pool: pool,
-        })
+            code_generator: settings.code_generator(),
+            settings,
+            catalog,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::commands::test_interaction;
+    use crate::services::database::test_database::TestDatabase;
+    use crate::services::localization::test_catalog;
+    use crate::services::registration_code::CodeFormat;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn registers_the_invoking_users() {
+        let database = TestDatabase::new().await;
+        let command = RegisterCommand::new(
+            Arc::clone(&database.pool),
+            RegistrationSettings {
+                code_ttl_minutes: 15,
+                code_format: CodeFormat::Short,
+                code_length: None,
+            },
+            Arc::new(test_catalog::catalog()),
+        );
+        let ctx = test_interaction::context();
+
+        command
+            .run(&ctx, &test_interaction::command(NAME, 1001), &CommandArguments::default())
+            .await
+            .unwrap();
+        command
+            .run(&ctx, &test_interaction::command(NAME, 1002), &CommandArguments::default())
+            .await
+            .unwrap();
+
+        let connection = database.pool.get().await.unwrap();
+        let rows = connection
+            .query("SELECT discordname FROM Players ORDER BY discordname", &[])
+            .await
+            .unwrap();
+        let discord_names: Vec<String> = rows.iter().map(|r| r.get("discordname")).collect();
+        drop(connection);
+        database.drop().await;
+
+        assert_eq!(discord_names, vec!["1001".to_string(), "1002".to_string()]);
+    }
+
+    // Hands out the same code until it has been handed out twice
+    struct CollidingCodeGenerator {
+        generated: AtomicUsize,
+    }
+
+    impl CodeGenerator for CollidingCodeGenerator {
+        fn generate(&self) -> String {
+            match self.generated.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => "SAME".to_string(),
+                _ => "OTHER".to_string(),
+            }
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn retries_registration_code_already_in_use() {
+        let database = TestDatabase::new().await;
+        let command = RegisterCommand {
+            db_connection_pool: Arc::clone(&database.pool),
+            settings: RegistrationSettings {
+                code_ttl_minutes: 15,
+                code_format: CodeFormat::Short,
+                code_length: None,
+            },
+            code_generator: Box::new(CollidingCodeGenerator {
+                generated: AtomicUsize::new(0),
+            }),
+            catalog: Arc::new(test_catalog::catalog()),
+        };
+        let ctx = test_interaction::context();
+
+        for user_id in [1001, 1002] {
+            command
+                .run(&ctx, &test_interaction::command(NAME, user_id), &CommandArguments::default())
+                .await
+                .unwrap();
+        }
+
+        let connection = database.pool.get().await.unwrap();
+        let rows = connection
+            .query(
+                "SELECT registrationcode = HashRegistrationCode('OTHER') AS retried FROM Players ORDER BY discordname",
+                &[],
+            )
+            .await
+            .unwrap();
+        let retried: Vec<bool> = rows.iter().map(|r| r.get("retried")).collect();
+        drop(connection);
+        database.drop().await;
+
+        assert_eq!(retried, vec![false, true]);
     }
 }
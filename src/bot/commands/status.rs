@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use crate::services::database::AuthenticationState;
+use crate::services::database::Database;
+use crate::services::database::DatabaseError;
+use crate::services::embed::{minecraft_avatar_url, EmbedData, ResponseData};
+use crate::services::localization::{Catalog, MessageParameters};
+
+use super::super::arguments::CommandArguments;
+use super::super::command::SlashCommand;
+use super::user_locale;
+use async_trait::async_trait;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::Context;
+use serenity::utils::Colour;
+
+use std::error::Error;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+static NAME: &str = "status";
+static DESCRIPTION: &str = "Show your Minecraft registration and session status";
+static RECENT_REQUESTS: i64 = 5;
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("status.session-active", &["expiration", "ip_address"]),
+    ("status.request", &["created", "ip_address", "minecraft_server", "state"]),
+];
+
+pub struct StatusCommand {
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    catalog: Arc<Catalog>,
+}
+
+#[async_trait]
+impl SlashCommand for StatusCommand {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        DESCRIPTION.to_string()
+    }
+
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    fn defer(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        _: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+
+        let discord_id = command.user.id.to_string();
+        let locale = &user_locale(&database, command).await?;
+        let catalog = &self.catalog;
+
+        match database.is_player_registered(&discord_id).await {
+            Ok(_) => (),
+            Err(DatabaseError::PlayerNotRegistered(_)) => {
+                return Ok(EmbedData {
+                    title: Some(catalog.message(locale, "status.title", &[])),
+                    description: Some(catalog.message(locale, "status.not-registered", &[])),
+                    colour: Some(Colour::RED),
+                    ..Default::default()
+                }.into())
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        let minecraft_user = match database.get_minecraft_user(&discord_id).await {
+            Ok(minecraft_user) => minecraft_user,
+            Err(DatabaseError::MissingMinecraftId(_)) => {
+                return Ok(EmbedData {
+                    title: Some(catalog.message(locale, "status.title", &[])),
+                    description: Some(catalog.message(locale, "status.pending", &[])),
+                    colour: Some(Colour::ORANGE),
+                    ..Default::default()
+                }.into())
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let session = match database.get_player_session(&discord_id).await? {
+            Some(session) => catalog.message(
+                locale,
+                "status.session-active",
+                &[
+                    ("expiration", &session.expiration.to_string()),
+                    (
+                        "ip_address",
+                        &session.ip_address.unwrap_or_else(|| catalog.message(locale, "common.unknown", &[])),
+                    ),
+                ],
+            ),
+            None => catalog.message(locale, "common.none", &[]),
+        };
+
+        let requests = database
+            .get_recent_authentication_requests(&minecraft_user, None, &RECENT_REQUESTS)
+            .await?;
+        let requests = if requests.is_empty() {
+            catalog.message(locale, "common.none", &[])
+        } else {
+            requests
+                .iter()
+                .map(|r| {
+                    catalog.message(
+                        locale,
+                        "status.request",
+                        &[
+                            ("created", &r.created.to_string()),
+                            ("ip_address", &r.ip_address),
+                            ("minecraft_server", &r.minecraft_server),
+                            ("state", &describe_state(catalog, locale, &r.state)),
+                        ],
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        };
+
+        Ok(EmbedData {
+            title: Some(catalog.message(locale, "status.title", &[])),
+            colour: Some(Colour::DARK_GREEN),
+            thumbnail: Some(minecraft_avatar_url(&minecraft_user)),
+            ..Default::default()
+        }
+        .field(catalog.message(locale, "status.registered", &[]), catalog.message(locale, "common.yes", &[]), true)
+        .field(catalog.message(locale, "status.minecraft-name", &[]), &minecraft_user, true)
+        .field(catalog.message(locale, "status.session", &[]), session, false)
+        .field(catalog.message(locale, "status.recent-requests", &[]), requests, false)
+        .into())
+    }
+}
+
+pub(super) fn describe_state(catalog: &Catalog, locale: &str, state: &AuthenticationState) -> String {
+    let id = match state {
+        AuthenticationState::Pending => "state.pending",
+        AuthenticationState::Prompted => "state.prompted",
+        AuthenticationState::Approved => "state.approved",
+        AuthenticationState::Denied => "state.denied",
+        AuthenticationState::Expired => "state.expired",
+        AuthenticationState::FailedDelivery => "state.failed-delivery",
+        AuthenticationState::Throttled => "state.throttled",
+    };
+
+    catalog.message(locale, id, &[])
+}
+
+impl StatusCommand {
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        let pool = Arc::clone(&db_connection_pool);
+        StatusCommand {
+            db_connection_pool: pool,
+            catalog,
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+
+use crate::services::database::Database;
+use crate::services::database::DatabaseError;
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::{Catalog, MessageParameters};
+
+use super::super::arguments::CommandArguments;
+use super::super::command::{CommandOption, OptionKind, SlashCommand, SubCommand};
+use super::user_locale;
+use async_trait::async_trait;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::Context;
+use serenity::utils::Colour;
+
+use std::error::Error;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+static NAME: &str = "trusted";
+static DESCRIPTION: &str = "Manage the networks you can log in from without approving";
+static LIST: &str = "list";
+static REVOKE: &str = "revoke";
+static ADDRESS: &str = "address";
+pub static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("trusted.network", &["expiration", "ip_address"]),
+    ("trusted.revoked", &["ip_address"]),
+    ("trusted.not-trusted", &["ip_address"]),
+];
+
+pub struct TrustedCommand {
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    catalog: Arc<Catalog>,
+}
+
+#[async_trait]
+impl SlashCommand for TrustedCommand {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        DESCRIPTION.to_string()
+    }
+
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    fn subcommands(&self) -> Vec<SubCommand> {
+        vec![
+            SubCommand {
+                name: LIST,
+                description: "List your trusted networks",
+                options: Vec::new(),
+            },
+            SubCommand {
+                name: REVOKE,
+                description: "Stop trusting a network",
+                options: vec![CommandOption {
+                    name: ADDRESS,
+                    description: "The IP address of the network",
+                    kind: OptionKind::String,
+                    required: true,
+                    choices: Vec::new(),
+                    autocomplete: false,
+                }],
+            },
+        ]
+    }
+
+    async fn run(
+        &self,
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        arguments: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+        let discord_id = command.user.id.to_string();
+        let locale = &user_locale(&database, command).await?;
+        let catalog = &self.catalog;
+
+        if arguments.subcommand() == Some(LIST) {
+            let addresses = database.get_trusted_addresses(&discord_id).await?;
+            let description = if addresses.is_empty() {
+                catalog.message(locale, "trusted.none", &[])
+            } else {
+                addresses
+                    .iter()
+                    .map(|a| {
+                        catalog.message(
+                            locale,
+                            "trusted.network",
+                            &[("ip_address", &a.ip_address), ("expiration", &a.expiration.to_string())],
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            };
+
+            return Ok(EmbedData {
+                title: Some(catalog.message(locale, "trusted.title", &[])),
+                description: Some(description),
+                colour: Some(Colour::DARK_GREEN),
+                ..Default::default()
+            }.into());
+        }
+
+        let address = match arguments.get_string(ADDRESS) {
+            Some(address) => address,
+            None => return Err("Missing address".into()),
+        };
+
+        match database.delete_trusted_address(&discord_id, address).await {
+            Ok(()) => Ok(EmbedData {
+                title: Some(catalog.message(locale, "trusted.title", &[])),
+                description: Some(catalog.message(locale, "trusted.revoked", &[("ip_address", address)])),
+                colour: Some(Colour::DARK_GREEN),
+                ..Default::default()
+            }.into()),
+            Err(DatabaseError::MissingTrustedAddress(_)) => Ok(EmbedData {
+                title: Some(catalog.message(locale, "trusted.title", &[])),
+                description: Some(catalog.message(locale, "trusted.not-trusted", &[("ip_address", address)])),
+                colour: Some(Colour::RED),
+                ..Default::default()
+            }.into()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+}
+
+impl TrustedCommand {
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        let pool = Arc::clone(&db_connection_pool);
+        TrustedCommand {
+            db_connection_pool: pool,
+            catalog,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use crate::services::database::Database;
+use crate::services::database::DatabaseError;
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::Catalog;
+
+use super::super::arguments::CommandArguments;
+use super::super::command::SlashCommand;
+use super::user_locale;
+use async_trait::async_trait;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::prelude::Context;
+use serenity::utils::Colour;
+
+use std::error::Error;
+
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+static NAME: &str = "unlock";
+static DESCRIPTION: &str = "Unlock your Minecraft account so you receive login requests again";
+
+pub struct UnlockCommand {
+    db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    catalog: Arc<Catalog>,
+}
+
+#[async_trait]
+impl SlashCommand for UnlockCommand {
+    fn name(&self) -> String {
+        NAME.to_string()
+    }
+
+    fn description(&self) -> String {
+        DESCRIPTION.to_string()
+    }
+
+    fn ephemeral(&self) -> bool {
+        true
+    }
+
+    async fn run(
+        &self,
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        _: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
+        let pool = Arc::clone(&self.db_connection_pool);
+        let database = Database::new(pool).await?;
+
+        let discord_id = command.user.id.to_string();
+        let locale = &user_locale(&database, command).await?;
+        let catalog = &self.catalog;
+
+        let locked = match database.is_player_locked(&discord_id).await {
+            Ok(locked) => locked,
+            Err(DatabaseError::PlayerNotRegistered(_)) => {
+                return Ok(EmbedData {
+                    title: Some(catalog.message(locale, "unlock.title", &[])),
+                    description: Some(catalog.message(locale, "unlock.not-registered", &[])),
+                    colour: Some(Colour::RED),
+                    ..Default::default()
+                }.into())
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        if !locked {
+            return Ok(EmbedData {
+                title: Some(catalog.message(locale, "unlock.title", &[])),
+                description: Some(catalog.message(locale, "unlock.not-locked", &[])),
+                colour: Some(Colour::ORANGE),
+                ..Default::default()
+            }.into());
+        }
+
+        database.set_player_locked(&discord_id, &false).await?;
+
+        Ok(EmbedData {
+            title: Some(catalog.message(locale, "unlock.title", &[])),
+            description: Some(catalog.message(locale, "unlock.unlocked", &[])),
+            colour: Some(Colour::DARK_GREEN),
+            ..Default::default()
+        }.into())
+    }
+}
+
+impl UnlockCommand {
+    pub fn new(
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        catalog: Arc<Catalog>,
+    ) -> Self {
+        let pool = Arc::clone(&db_connection_pool);
+        UnlockCommand {
+            db_connection_pool: pool,
+            catalog,
+        }
+    }
+}
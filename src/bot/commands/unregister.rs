@@ -2,11 +2,14 @@ use std::sync::Arc;
 
 use crate::services::database::Database;
 use crate::services::database::DatabaseError;
-use crate::services::embed::EmbedData;
+use crate::services::embed::{EmbedData, ResponseData};
+use crate::services::localization::Catalog;
 
+use super::super::arguments::CommandArguments;
 use super::super::command::SlashCommand;
+use super::user_locale;
 use async_trait::async_trait;
-use serenity::model::prelude::interaction::application_command::CommandDataOption;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::prelude::Context;
 use serenity::utils::Colour;
 
@@ -21,6 +24,7 @@ static DESCRIPTION: &str = "Unregister yourself from the Minecraft server";
 
 pub struct UnregisterCommand {
     db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+    catalog: Arc<Catalog>,
 }
 
 #[async_trait]
@@ -35,38 +39,42 @@ impl SlashCommand for UnregisterCommand {
 
     async fn run(
         &self,
-        ctx: &Context,
-        _: &[CommandDataOption],
-    ) -> Result<EmbedData, Box<dyn Error>> {
+        _: &Context,
+        command: &ApplicationCommandInteraction,
+        _: &CommandArguments,
+    ) -> Result<ResponseData, Box<dyn Error>> {
         let pool = Arc::clone(&self.db_connection_pool);
         let database = Database::new(pool).await?;
 
-        let discord_user = ctx.http.get_current_user().await?;
+        let discord_user = &command.user;
+        let locale = &user_locale(&database, command).await?;
         let is_player_registered = database
             .is_player_registered(&discord_user.id.to_string())
             .await;
         match is_player_registered {
-            Ok(_) => match database.delete_player(&discord_user.id.to_string()).await {
-                Ok(_) => {
-                    return Ok(EmbedData {
-                        title: Some("Minecraft unregistration".to_string()),
-                        description: Some(
-                            "You have been successfully unregistered from the Minecraft server"
-                                .to_string(),
-                        ),
-                        colour: Some(Colour::DARK_GREEN),
-                    })
+            Ok(_) => {
+                // Disconnects the player from the Minecraft server, which only learns about revoked sessions
+                database.revoke_player_session(&discord_user.id.to_string()).await?;
+
+                match database.delete_player(&discord_user.id.to_string()).await {
+                    Ok(_) => {
+                        return Ok(EmbedData {
+                            title: Some(self.catalog.message(locale, "unregister.title", &[])),
+                            description: Some(self.catalog.message(locale, "unregister.success", &[])),
+                            colour: Some(Colour::DARK_GREEN),
+                            ..Default::default()
+                        }.into())
+                    }
+                    Err(e) => return Err(Box::new(e)),
                 }
-                Err(e) => return Err(Box::new(e)),
-            },
+            }
             Err(DatabaseError::PlayerNotRegistered(_)) => {
                 return Ok(EmbedData {
-                    title: Some("Minecraft unregistration".to_string()),
-                    description: Some(
-                        "You are not registered on the Minecraft server.".to_string(),
-                    ),
+                    title: Some(self.catalog.message(locale, "unregister.title", &[])),
+                    description: Some(self.catalog.message(locale, "unregister.not-registered", &[])),
                     colour: Some(Colour::RED),
-                });
+                    ..Default::default()
+                }.into());
             }
             Err(e) => Err(Box::new(e)),
         }
@@ -76,10 +84,12 @@ impl SlashCommand for UnregisterCommand {
 impl UnregisterCommand {
     pub fn new(
         db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
-    ) -> Box<dyn SlashCommand + 'static> {
+        catalog: Arc<Catalog>,
+    ) -> Self {
         let pool = Arc::clone(&db_connection_pool);
-        Box::new(UnregisterCommand {
+        UnregisterCommand {
             db_connection_pool: pool,
-        })
+            catalog,
+        }
     }
 }
@@ -1,4 +1,17 @@
+use crate::services::localization::MessageParameters;
+
+pub mod arguments;
 pub mod authentication;
 pub mod bot;
 pub mod command;
 pub mod commands;
+
+// Every message the bot renders with variables, checked against the templates when the catalog is loaded
+pub fn message_parameters() -> Vec<MessageParameters> {
+    [
+        authentication::MESSAGE_PARAMETERS,
+        bot::MESSAGE_PARAMETERS,
+        &commands::message_parameters(),
+    ]
+    .concat()
+}
@@ -0,0 +1,397 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use serenity::http::Http;
+use serenity::model::id::GuildId;
+use thiserror::Error;
+
+use crate::bot::bot::register_commands;
+use crate::bot::{self, commands};
+use crate::services::database::{self, Database, MinecraftServer};
+use crate::services::localization::Catalog;
+use crate::services::migrations::{self, MigrationError, MigrationPlan};
+use crate::services::settings::{PostgresSettings, Settings};
+
+pub static USAGE: &str = "Usage: minecraft-discord-auth [COMMAND]
+
+Commands:
+  run                                              Start the bot (default)
+  migrate [--dry-run]                              Apply the pending database migrations
+  check-config                                     Validate the configuration and message templates
+  register-commands                                Replace the slash commands of the guild
+  list-players                                     List the registered players
+  revoke <discord-id>                              End the Minecraft session of a player
+  export                                           Print everything stored in the database as JSON
+  simulate-login <minecraft-name> <ip> <server>    Create a login request as a Minecraft server would
+  server <name> [<rule> <value>]                   Show or change the access rules of a Minecraft server
+  help                                             Show this message
+
+Rules of a Minecraft server:
+  required-role <role-id|none>                     Discord role a player needs to join
+  session-minutes <minutes|none>                   Session length, takes precedence over the authentication settings
+  require-approval <true|false>                    Whether logins have to be approved in Discord
+  maintenance <true|false>                         Deny every login";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum CliError {
+    #[error("Unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("Missing argument <{argument}> for '{command}'")]
+    MissingArgument {
+        command: &'static str,
+        argument: &'static str,
+    },
+    #[error("Unexpected argument '{argument}' for '{command}'")]
+    UnexpectedArgument {
+        command: &'static str,
+        argument: String,
+    },
+    #[error("Unknown rule '{0}'")]
+    UnknownRule(String),
+    #[error("Invalid value '{value}' for the rule '{rule}'")]
+    InvalidValue { rule: &'static str, value: String },
+}
+
+// A change to one of the access rules of a Minecraft server
+#[derive(Debug, PartialEq, Eq)]
+pub enum ServerRule {
+    RequiredRole(Option<String>),
+    SessionMinutes(Option<i32>),
+    RequireApproval(bool),
+    Maintenance(bool),
+}
+
+impl ServerRule {
+    fn parse(rule: &str, value: &str) -> Result<ServerRule, CliError> {
+        let invalid = |rule: &'static str| CliError::InvalidValue {
+            rule,
+            value: value.to_string(),
+        };
+
+        match rule {
+            "required-role" => optional(value, |v| v.parse::<u64>().ok().map(|_| v.to_string()))
+                .map(ServerRule::RequiredRole)
+                .ok_or_else(|| invalid("required-role")),
+            "session-minutes" => optional(value, |v| v.parse::<i32>().ok().filter(|minutes| *minutes > 0))
+                .map(ServerRule::SessionMinutes)
+                .ok_or_else(|| invalid("session-minutes")),
+            "require-approval" => value
+                .parse::<bool>()
+                .map(ServerRule::RequireApproval)
+                .map_err(|_| invalid("require-approval")),
+            "maintenance" => value
+                .parse::<bool>()
+                .map(ServerRule::Maintenance)
+                .map_err(|_| invalid("maintenance")),
+            _ => Err(CliError::UnknownRule(rule.to_string())),
+        }
+    }
+
+    fn apply(self, server: &mut MinecraftServer) {
+        match self {
+            ServerRule::RequiredRole(role_id) => server.required_role_id = role_id,
+            ServerRule::SessionMinutes(minutes) => server.session_minutes = minutes,
+            ServerRule::RequireApproval(require_approval) => server.require_approval = require_approval,
+            ServerRule::Maintenance(maintenance) => server.maintenance = maintenance,
+        }
+    }
+}
+
+// none clears a rule, anything else has to be a valid value
+fn optional<T>(value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Option<T>> {
+    match value {
+        "none" => Some(None),
+        value => parse(value).map(Some),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Run,
+    Migrate { dry_run: bool },
+    CheckConfig,
+    RegisterCommands,
+    ListPlayers,
+    Revoke { discord_id: String },
+    Export,
+    SimulateLogin {
+        minecraft_user: String,
+        ip_address: String,
+        minecraft_server: String,
+    },
+    Server {
+        name: String,
+        rule: Option<ServerRule>,
+    },
+    Help,
+}
+
+impl Command {
+    // The arguments without the name of the binary
+    pub fn parse(arguments: &[String]) -> Result<Command, CliError> {
+        let (name, mut arguments) = match arguments.split_first() {
+            Some((name, arguments)) => (name.as_str(), arguments.iter()),
+            None => return Ok(Command::Run),
+        };
+
+        let command = match name {
+            "run" => Command::Run,
+            "migrate" => match arguments.next().map(String::as_str) {
+                Some("--dry-run") => Command::Migrate { dry_run: true },
+                Some(argument) => {
+                    return Err(CliError::UnexpectedArgument {
+                        command: "migrate",
+                        argument: argument.to_string(),
+                    })
+                }
+                None => Command::Migrate { dry_run: false },
+            },
+            "check-config" => Command::CheckConfig,
+            "register-commands" => Command::RegisterCommands,
+            "list-players" => Command::ListPlayers,
+            "revoke" => Command::Revoke {
+                discord_id: required(&mut arguments, "revoke", "discord-id")?,
+            },
+            "export" => Command::Export,
+            "simulate-login" => Command::SimulateLogin {
+                minecraft_user: required(&mut arguments, "simulate-login", "minecraft-name")?,
+                ip_address: required(&mut arguments, "simulate-login", "ip")?,
+                minecraft_server: required(&mut arguments, "simulate-login", "server")?,
+            },
+            "server" => Command::Server {
+                name: required(&mut arguments, "server", "name")?,
+                rule: match arguments.next() {
+                    Some(rule) => Some(ServerRule::parse(rule, &required(&mut arguments, "server", "value")?)?),
+                    None => None,
+                },
+            },
+            "help" | "--help" | "-h" => Command::Help,
+            _ => return Err(CliError::UnknownCommand(name.to_string())),
+        };
+
+        match arguments.next() {
+            Some(argument) => Err(CliError::UnexpectedArgument {
+                command: command.name(),
+                argument: argument.clone(),
+            }),
+            None => Ok(command),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Run => "run",
+            Command::Migrate { .. } => "migrate",
+            Command::CheckConfig => "check-config",
+            Command::RegisterCommands => "register-commands",
+            Command::ListPlayers => "list-players",
+            Command::Revoke { .. } => "revoke",
+            Command::Export => "export",
+            Command::SimulateLogin { .. } => "simulate-login",
+            Command::Server { .. } => "server",
+            Command::Help => "help",
+        }
+    }
+
+    // The other commands only need the database, so they work without the Discord settings
+    pub fn uses_discord(&self) -> bool {
+        matches!(self, Command::Run | Command::CheckConfig | Command::RegisterCommands)
+    }
+}
+
+fn required<'a>(
+    arguments: &mut impl Iterator<Item = &'a String>,
+    command: &'static str,
+    argument: &'static str,
+) -> Result<String, CliError> {
+    arguments
+        .next()
+        .cloned()
+        .ok_or(CliError::MissingArgument { command, argument })
+}
+
+// Runs every command except for run and help, which are handled by main
+pub async fn execute(command: Command, settings: &Settings) -> Result<(), Box<dyn Error>> {
+    match command {
+        Command::Migrate { dry_run } => print_plan(&migrate(&settings.postgres, dry_run).await?, dry_run),
+        Command::CheckConfig => {
+            Catalog::load(&settings.localization, &bot::message_parameters())
+                .map_err(|e| format!("Invalid message templates: {}", e))?;
+            println!("The configuration is valid: {:#?}", settings);
+        }
+        Command::RegisterCommands => {
+            let discord = settings.discord()?;
+            let http = Http::new(&discord.token);
+            http.set_application_id(http.get_current_application_info().await?.id.0);
+
+            let pool = Arc::new(database::get_connection_pool(&settings.postgres).await);
+            let catalog = Arc::new(Catalog::load(&settings.localization, &bot::message_parameters())?);
+            let commands = commands::get_commands(pool, settings, Arc::clone(&catalog));
+            let registered = register_commands(&http, GuildId(discord.guild_id), &commands, &catalog).await?;
+
+            for command in registered.iter() {
+                println!("Registered /{}", command.name);
+            }
+        }
+        Command::ListPlayers => {
+            let players = database(settings).await?.get_players().await?;
+
+            for player in players.iter() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    player.discord_id,
+                    player.minecraft_user.as_deref().unwrap_or("(pending registration)"),
+                    if player.locked { "locked" } else { "unlocked" },
+                    match player.session_expiration {
+                        Some(expiration) => format!("session until {}", expiration),
+                        None => "no session".to_string(),
+                    }
+                );
+            }
+
+            println!("{} players", players.len());
+        }
+        Command::Revoke { discord_id } => {
+            if database(settings).await?.revoke_player_session(&discord_id).await?.is_some() {
+                println!("The session of {} has been revoked", discord_id);
+            } else {
+                println!("{} does not have a session", discord_id);
+            }
+        }
+        Command::Export => println!("{}", database(settings).await?.export().await?),
+        Command::SimulateLogin {
+            minecraft_user,
+            ip_address,
+            minecraft_server,
+        } => {
+            let database = database(settings).await?;
+
+            if database.find_discord_id(&minecraft_user).await?.is_none() {
+                return Err(format!("No Discord user has linked the Minecraft name {}", minecraft_user).into());
+            }
+
+            let request_id = database
+                .add_authentication_request(&minecraft_user, &ip_address, &minecraft_server)
+                .await?;
+            println!("Created authentication request {}, the running bot will prompt the player", request_id);
+        }
+        Command::Server { name, rule } => {
+            let database = database(settings).await?;
+            let mut server = database.get_or_add_minecraft_server(&name).await?;
+
+            if let Some(rule) = rule {
+                rule.apply(&mut server);
+                database.set_minecraft_server(&server).await?;
+            }
+
+            print_server(&server, settings);
+        }
+        Command::Run | Command::Help => unreachable!("{} is handled by main", command.name()),
+    }
+
+    Ok(())
+}
+
+async fn database(settings: &Settings) -> Result<Database, Box<dyn Error>> {
+    Database::new(Arc::new(database::get_connection_pool(&settings.postgres).await)).await
+}
+
+pub async fn migrate(settings: &PostgresSettings, dry_run: bool) -> Result<MigrationPlan, MigrationError> {
+    let (mut client, connection) = tokio_postgres::connect(&settings.url, tokio_postgres::NoTls).await?;
+    tokio::spawn(connection);
+
+    migrations::migrate(&mut client, dry_run).await
+}
+
+// The applied migrations have already been logged while migrating
+fn print_plan(plan: &MigrationPlan, dry_run: bool) {
+    if plan.adopts_baseline {
+        println!("The existing tables {} recorded as migration 1 (baseline)", if dry_run { "would be" } else { "were" });
+    }
+
+    if plan.pending.is_empty() {
+        println!("The database is up to date (version {})", migrations::latest_version());
+    } else if dry_run {
+        for migration in plan.pending.iter() {
+            println!("Would apply migration {} ({})", migration.version, migration.name);
+        }
+    }
+}
+
+fn print_server(server: &MinecraftServer, settings: &Settings) {
+    let configured_minutes = settings.authentication.for_server(&server.name).session_minutes;
+
+    println!("Minecraft server {}", server.name);
+    println!("  required-role      {}", server.required_role_id.as_deref().unwrap_or("none"));
+    println!(
+        "  session-minutes    {}",
+        match server.session_minutes {
+            Some(minutes) => format!("{} (instead of {} from the authentication settings)", minutes, configured_minutes),
+            None => format!("none ({} from the authentication settings)", configured_minutes),
+        }
+    );
+    println!("  require-approval   {}", server.require_approval);
+    println!("  maintenance        {}", server.maintenance);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &[&str]) -> Result<Command, CliError> {
+        Command::parse(&arguments.iter().map(|a| a.to_string()).collect::<Vec<String>>())
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse(&[]), Ok(Command::Run));
+        assert_eq!(parse(&["migrate", "--dry-run"]), Ok(Command::Migrate { dry_run: true }));
+        assert_eq!(
+            parse(&["revoke", "1001"]),
+            Ok(Command::Revoke {
+                discord_id: "1001".to_string()
+            })
+        );
+        assert_eq!(
+            parse(&["simulate-login", "Steve", "127.0.0.1", "survival"]),
+            Ok(Command::SimulateLogin {
+                minecraft_user: "Steve".to_string(),
+                ip_address: "127.0.0.1".to_string(),
+                minecraft_server: "survival".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(&["server", "survival", "required-role", "none"]),
+            Ok(Command::Server {
+                name: "survival".to_string(),
+                rule: Some(ServerRule::RequiredRole(None)),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert_eq!(parse(&["start"]), Err(CliError::UnknownCommand("start".to_string())));
+        assert_eq!(
+            parse(&["simulate-login", "Steve", "127.0.0.1"]),
+            Err(CliError::MissingArgument {
+                command: "simulate-login",
+                argument: "server"
+            })
+        );
+        assert_eq!(
+            parse(&["server", "survival", "session-minutes", "0"]),
+            Err(CliError::InvalidValue {
+                rule: "session-minutes",
+                value: "0".to_string()
+            })
+        );
+        assert_eq!(
+            parse(&["export", "players.json"]),
+            Err(CliError::UnexpectedArgument {
+                command: "export",
+                argument: "players.json".to_string()
+            })
+        );
+    }
+}
@@ -1,32 +1,86 @@
 pub mod bot;
+mod cli;
 pub mod services;
 
+use cli::Command;
 use dotenvy::dotenv;
 use services::database;
+use services::localization::Catalog;
 use services::queue::MessageQueue;
+use services::settings::Settings;
 use std::env;
+use std::process;
+use std::sync::Arc;
 use futures::channel::mpsc;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    // Login with a bot token from the environment
-    let token = env::var("DISCORD_TOKEN").expect("Could not retrieve DISCORD_TOKEN");
-    let postgres_connection_string =
-        env::var("POSTGRES_URL").expect("Could not retrieve POSTGRES_URL");
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    let command = match Command::parse(&arguments) {
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(command) => command,
+        Err(e) => {
+            println!("{}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+
+    let settings = match Settings::load() {
+        Ok(settings) => settings,
+        Err(e) => {
+            println!("Invalid configuration: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if command.uses_discord() {
+        if let Err(e) = settings.discord() {
+            println!("Invalid configuration: {}", e);
+            process::exit(1);
+        }
+    }
+
+    if command != Command::Run {
+        if let Err(e) = cli::execute(command, &settings).await {
+            println!("{}", e);
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    let catalog = match Catalog::load(&settings.localization, &bot::message_parameters()) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            println!("Invalid message templates: {}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = cli::migrate(&settings.postgres, false).await {
+        println!("Could not migrate the database: {}", e);
+        process::exit(1);
+    }
+    let postgres_connection_string = settings.postgres.url.clone();
+    let queue_settings = settings.authentication.clone();
 
     let (tx, rx) = mpsc::channel(100);
 
-    let db_connection_pool = database::get_connection_pool(&postgres_connection_string).await;
+    let db_connection_pool = database::get_connection_pool(&settings.postgres).await;
+    let queue_connection_pool = Arc::new(db_connection_pool.clone());
     tokio::spawn(async move {
-        let mut queue = MessageQueue::new(&postgres_connection_string, tx)
+        let mut queue = MessageQueue::new(&postgres_connection_string, queue_connection_pool, tx, queue_settings)
             .await
             .unwrap();
         queue.start().await;
     });
-    let bot = bot::bot::Bot::new(token, db_connection_pool, rx)
+    let bot = bot::bot::Bot::new(&settings, db_connection_pool, rx, catalog)
         .await
-        .expect("Could not create bot!");
+    .expect("Could not create bot!");
 
     // start listening for events by starting a single shard
     if let Err(why) = bot.start().await {
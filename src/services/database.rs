@@ -1,28 +1,41 @@
-use bb8::Pool;
+use bb8::{Pool, PooledConnection};
 use bb8_postgres::PostgresConnectionManager;
 use std::error::Error;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio_postgres::Config;
 use tokio_postgres::NoTls;
 
+use super::settings::PostgresSettings;
+
 #[derive(Error, Debug)]
 pub enum DatabaseError {
     #[error("Could not insert {data:?} (Error: {why:?})")]
     InsertError { data: String, why: String },
+    #[error("Could not update {data:?} (Error: {why:?})")]
+    UpdateError { data: String, why: String },
     #[error("Could not delete {data:?} (Error: {why:?})")]
     DeleteError { data: String, why: String },
     #[error("Could not select {data:?} (Error: {why:?})")]
     SelectError { data: String, why: String },
+    #[error("Could not notify {data:?} (Error: {why:?})")]
+    NotifyError { data: String, why: String },
+    #[error("Could not get a database connection (Error: {0:?})")]
+    ConnectionError(String),
     #[error("Could not find a Discord user with the Minecraft user name '{0}'")]
     MissingDiscordId(String),
     #[error("Could not find a Minecraft player for Discord user with the id '{0}'")]
     MissingMinecraftId(String),
     #[error("Could not find a registration code for Discord user with the id '{0}'")]
     MissingRegistration(String),
+    #[error("The registration code is already in use")]
+    DuplicateRegistrationCode,
     #[error("Could not find an authentication request with the id '{0}'")]
     MissingAuthenticationRequest(String),
+    #[error("The network '{0}' is not trusted")]
+    MissingTrustedAddress(String),
     #[error("The Discord user with the id '{0}' has not been registered on the Minecraft server")]
     PlayerNotRegistered(String),
     #[error(
@@ -31,18 +44,120 @@ pub enum DatabaseError {
     PlayerNotAuthenticated(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthenticationState {
+    Pending,
+    Prompted,
+    Approved,
+    Denied,
+    Expired,
+    // The request could not be delivered to, or completed for, the Discord user
+    FailedDelivery,
+    // Too many login requests were made for the Minecraft user or from the IP address
+    Throttled,
+}
+
+impl AuthenticationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuthenticationState::Pending => "pending",
+            AuthenticationState::Prompted => "prompted",
+            AuthenticationState::Approved => "approved",
+            AuthenticationState::Denied => "denied",
+            AuthenticationState::Expired => "expired",
+            AuthenticationState::FailedDelivery => "failed_delivery",
+            AuthenticationState::Throttled => "throttled",
+        }
+    }
+
+    pub fn parse(state: &str) -> Option<AuthenticationState> {
+        match state {
+            "pending" => Some(AuthenticationState::Pending),
+            "prompted" => Some(AuthenticationState::Prompted),
+            "approved" => Some(AuthenticationState::Approved),
+            "denied" => Some(AuthenticationState::Denied),
+            "expired" => Some(AuthenticationState::Expired),
+            "failed_delivery" => Some(AuthenticationState::FailedDelivery),
+            "throttled" => Some(AuthenticationState::Throttled),
+            _ => None,
+        }
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        !matches!(
+            self,
+            AuthenticationState::Pending | AuthenticationState::Prompted
+        )
+    }
+}
+
+// Timestamps are in seconds since the Unix epoch
+#[derive(Debug)]
+pub struct PlayerSession {
+    pub expiration: i64,
+    pub ip_address: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct AuthenticationRequest {
+    pub minecraft_server: String,
+    pub ip_address: String,
+    pub state: AuthenticationState,
+    pub created: i64,
+}
+
+// A request that has not reached a final state, e.g. because the bot stopped while it was pending or prompted
+#[derive(Debug)]
+pub struct UnhandledAuthenticationRequest {
+    pub id: i32,
+    pub minecraft_server: String,
+    pub state: AuthenticationState,
+    // Seconds since the request was made or prompted
+    pub state_age: i64,
+}
+
+// Access rules for a Minecraft server, new servers are added with the default rules on their first login request
+#[derive(Debug)]
+pub struct MinecraftServer {
+    pub name: String,
+    pub required_role_id: Option<String>,
+    // Takes precedence over the session length of the authentication settings when set
+    pub session_minutes: Option<i32>,
+    pub require_approval: bool,
+    pub maintenance: bool,
+}
+
+#[derive(Debug)]
+pub struct TrustedAddress {
+    pub ip_address: String,
+    pub expiration: i64,
+}
+
+// A registered player as listed by the command line, timestamps are in seconds since the Unix epoch
+#[derive(Debug)]
+pub struct PlayerSummary {
+    pub discord_id: String,
+    pub minecraft_user: Option<String>,
+    pub locked: bool,
+    pub session_expiration: Option<i64>,
+}
+
 pub struct Database {
     pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
 }
 
 impl Database {
-    pub async fn add_player(&self, discord_id: &str, reg_code: &str) -> Result<(), DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+    pub async fn add_player(
+        &self,
+        discord_id: &str,
+        reg_code: &str,
+        code_ttl_minutes: &i32,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
         let result = connection
             .execute(
-                "INSERT INTO Players(discordname, registrationcode) VALUES($1, $2)",
-                &[&discord_id, &reg_code],
+                "INSERT INTO Players(discordname, registrationcode, registrationcodeexpiration) VALUES($1, HashRegistrationCode($2), now()::timestamp + ($3::INT * INTERVAL '1 minute'))",
+                &[&discord_id, &reg_code, &code_ttl_minutes],
             )
             .await;
 
@@ -52,6 +167,7 @@ impl Database {
                 why: "0 rows inserted!".to_string(),
             }),
             Ok(_) => Ok(()),
+            Err(e) if is_duplicate_registration_code(&e) => Err(DatabaseError::DuplicateRegistrationCode),
             Err(e) => Err(DatabaseError::InsertError {
                 data: "player".to_string(),
                 why: e.to_string(),
@@ -59,17 +175,44 @@ impl Database {
         }
     }
 
+    pub async fn add_trusted_address(
+        &self,
+        discord_id: &str,
+        ip_address: &str,
+        days: &i32,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "INSERT INTO TrustedAddresses(discordname, ipaddress, expiration) VALUES($1, $2, now()::timestamp + ($3::INT * INTERVAL '1 day')) ON CONFLICT (discordname, ipaddress) DO UPDATE SET created=now()::timestamp, expiration=EXCLUDED.expiration",
+                &[&discord_id, &ip_address, &days],
+            )
+            .await;
+
+        match result {
+            Ok(0) => Err(DatabaseError::InsertError {
+                data: "Trusted address".to_string(),
+                why: "0 rows inserted!".to_string(),
+            }),
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::InsertError {
+                data: "Trusted address".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
     pub async fn add_player_auth(
         &self,
         discord_id: &str,
         auth_request_id: &i32,
+        session_minutes: &i32,
     ) -> Result<(), DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let result = connection
             .execute(
-                "INSERT INTO PlayerAuthentications(discordname, authrequestid) VALUES($1, $2)",
-                &[&discord_id, &auth_request_id],
+                "INSERT INTO PlayerAuthentications(discordname, authrequestid, expiration) VALUES($1, $2, now()::timestamp + ($3::INT * INTERVAL '1 minute'))",
+                &[&discord_id, &auth_request_id, &session_minutes],
             )
             .await;
 
@@ -90,8 +233,7 @@ impl Database {
     }
 
     pub async fn delete_player(&self, discord_id: &str) -> Result<(), DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let result = connection
             .execute("DELETE FROM Players WHERE discordname=$1", &[&discord_id])
             .await;
@@ -106,9 +248,31 @@ impl Database {
         }
     }
 
+    pub async fn delete_trusted_address(
+        &self,
+        discord_id: &str,
+        ip_address: &str,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "DELETE FROM TrustedAddresses WHERE discordname=$1 AND ipaddress=$2",
+                &[&discord_id, &ip_address],
+            )
+            .await;
+
+        match result {
+            Ok(0) => Err(DatabaseError::MissingTrustedAddress(ip_address.to_string())),
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::DeleteError {
+                data: "Trusted address".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
     pub async fn delete_player_auth(&self, discord_id: &str) -> Result<(), DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let result = connection
             .execute(
                 "DELETE FROM PlayerAuthentications WHERE discordname=$1",
@@ -126,9 +290,120 @@ impl Database {
         }
     }
 
+    // Ends the session of the player and tells the Minecraft servers about it.
+    // Returns the seconds that were left of the session, or None if the player was not logged in.
+    pub async fn revoke_player_session(&self, discord_id: &str) -> Result<Option<i64>, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "DELETE FROM PlayerAuthentications WHERE discordname=$1 RETURNING CAST(EXTRACT(EPOCH FROM (expiration - now()::timestamp)) AS BIGINT) AS remaining",
+                &[&discord_id],
+            )
+            .await;
+
+        let remaining = match row {
+            Ok(Some(r)) => r.get::<&str, i64>("remaining"),
+            Ok(None) => return Ok(None),
+            Err(e) => {
+                return Err(DatabaseError::DeleteError {
+                    data: "Player authentication".to_string(),
+                    why: e.to_string(),
+                })
+            }
+        };
+
+        // An expired session has already ended on the Minecraft server
+        if remaining < 0 {
+            return Ok(None);
+        }
+
+        if let Ok(minecraft_user) = self.get_minecraft_user(discord_id).await {
+            if let Err(e) = self.notify_revoked_auth(&minecraft_user).await {
+                println!("Could not notify the Minecraft server about the revoked session of {}: {}", minecraft_user, e);
+            }
+        }
+
+        Ok(Some(remaining))
+    }
+
+    // Inserted the same way the Minecraft servers do, which makes the bot prompt the player
+    pub async fn add_authentication_request(
+        &self,
+        minecraft_user_id: &str,
+        ip_address: &str,
+        minecraft_server: &str,
+    ) -> Result<i32, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "INSERT INTO AuthenticationRequests(minecraftname, ipaddress, minecraftserver) VALUES($1, $2, $3) RETURNING id",
+                &[&minecraft_user_id, &ip_address, &minecraft_server],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.get("id")),
+            Err(e) => Err(DatabaseError::InsertError {
+                data: "Authentication request".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_players(&self) -> Result<Vec<PlayerSummary>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT Players.discordname, Players.minecraftname, locked, CAST(EXTRACT(EPOCH FROM CAST(AuthenticatedPlayers.expiration AS TIMESTAMPTZ)) AS BIGINT) AS expiration FROM Players LEFT JOIN AuthenticatedPlayers USING (discordname) ORDER BY Players.discordname",
+                &[],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => Ok(rows
+                .iter()
+                .map(|r| PlayerSummary {
+                    discord_id: r.get("discordname"),
+                    minecraft_user: r.get("minecraftname"),
+                    locked: r.get("locked"),
+                    session_expiration: r.get("expiration"),
+                })
+                .collect()),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Players".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    // Everything the bot stores as a JSON document, except for the registration code hashes
+    pub async fn export(&self) -> Result<String, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "SELECT jsonb_pretty(jsonb_build_object(
+                    'players', (SELECT COALESCE(jsonb_agg(p ORDER BY p.discordname), '[]') FROM (SELECT discordname, minecraftname, locked, registrationcodecreated, registrationcodeexpiration FROM Players) p),
+                    'sessions', (SELECT COALESCE(jsonb_agg(s ORDER BY s.discordname), '[]') FROM (SELECT discordname, minecraftname, expiration, ipaddress FROM AuthenticatedPlayers) s),
+                    'authentication_requests', (SELECT COALESCE(jsonb_agg(r ORDER BY r.id), '[]') FROM (SELECT id, minecraftname, minecraftserver, ipaddress, state, created, statechanged FROM AuthenticationRequests) r),
+                    'trusted_addresses', (SELECT COALESCE(jsonb_agg(t ORDER BY t.id), '[]') FROM (SELECT id, discordname, ipaddress, created, expiration FROM TrustedAddresses) t),
+                    'minecraft_servers', (SELECT COALESCE(jsonb_agg(m ORDER BY m.name), '[]') FROM MinecraftServers m),
+                    'user_preferences', (SELECT COALESCE(jsonb_agg(u ORDER BY u.discordname), '[]') FROM UserPreferences u)
+                )) AS export",
+                &[],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.get("export")),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Export".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
     pub async fn get_discord_id(&self, minecraft_user_id: &str) -> Result<String, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let row = connection
             .query_one(
                 "SELECT discordname FROM Players WHERE minecraftname=$1",
@@ -163,9 +438,26 @@ impl Database {
         }
     }
 
+    pub async fn find_discord_id(&self, minecraft_user_id: &str) -> Result<Option<String>, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "SELECT discordname FROM Players WHERE minecraftname=$1",
+                &[&minecraft_user_id],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.map(|r| r.get("discordname"))),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Discord id".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
     pub async fn get_minecraft_user(&self, discord_id: &str) -> Result<String, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let row = connection
             .query_one(
                 "SELECT minecraftname FROM Players WHERE discordname=$1",
@@ -192,35 +484,20 @@ impl Database {
         };
     }
 
-    pub async fn get_reg_code(&self, discord_id: &str) -> Result<String, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+    pub async fn get_reg_code_expiration(&self, discord_id: &str) -> Result<i64, DatabaseError> {
+        let connection = self.connection().await?;
         let row = connection
-            .query_one(
-                "SELECT registrationcode FROM Players WHERE discordname=$1",
+            .query_opt(
+                "SELECT CAST(EXTRACT(EPOCH FROM CAST(registrationcodeexpiration AS TIMESTAMPTZ)) AS BIGINT) AS expiration FROM Players WHERE discordname=$1",
                 &[&discord_id],
             )
             .await;
 
         match row {
-            Ok(r) if r.is_empty() => {
-                Err(DatabaseError::MissingRegistration(discord_id.to_string()))
-            }
-            Ok(r) => {
-                let result: Result<Option<String>, tokio_postgres::Error> =
-                    r.try_get("registrationcode");
-
-                match result {
-                    Ok(Some(reg_code)) => Ok(reg_code),
-                    Ok(None) => Err(DatabaseError::MissingRegistration(discord_id.to_string())),
-                    Err(e) => Err(DatabaseError::SelectError {
-                        data: "registration code".to_string(),
-                        why: e.to_string(),
-                    }),
-                }
-            }
+            Ok(Some(r)) => Ok(r.get("expiration")),
+            Ok(None) => Err(DatabaseError::MissingRegistration(discord_id.to_string())),
             Err(e) => Err(DatabaseError::SelectError {
-                data: "Discord id".to_string(),
+                data: "registration code".to_string(),
                 why: e.to_string(),
             }),
         }
@@ -230,8 +507,7 @@ impl Database {
         &self,
         request_id: &i32,
     ) -> Result<String, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let row = connection
             .query_one(
                 "SELECT minecraftname FROM AuthenticationRequests WHERE id=$1",
@@ -269,8 +545,7 @@ impl Database {
         &self,
         request_id: &i32,
     ) -> Result<String, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let row = connection
             .query_one(
                 "SELECT ipaddress FROM AuthenticationRequests WHERE id=$1",
@@ -303,13 +578,513 @@ impl Database {
         }
     }
 
+    pub async fn get_authentication_request_server(
+        &self,
+        request_id: &i32,
+    ) -> Result<String, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "SELECT minecraftserver FROM AuthenticationRequests WHERE id=$1",
+                &[&request_id],
+            )
+            .await;
+
+        match row {
+            Ok(r) if r.is_empty() => Err(DatabaseError::MissingAuthenticationRequest(
+                request_id.to_string(),
+            )),
+            Ok(r) => {
+                let result: Result<Option<String>, tokio_postgres::Error> = r.try_get("minecraftserver");
+
+                match result {
+                    Ok(Some(minecraft_server)) => Ok(minecraft_server),
+                    Ok(None) => Err(DatabaseError::MissingAuthenticationRequest(
+                        request_id.to_string(),
+                    )),
+                    Err(e) => Err(DatabaseError::SelectError {
+                        data: "Authentication request".to_string(),
+                        why: e.to_string(),
+                    }),
+                }
+            }
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Authentication request".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_minecraft_server(
+        &self,
+        minecraft_server: &str,
+    ) -> Result<Option<MinecraftServer>, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "SELECT name, requiredroleid, sessionminutes, requireapproval, maintenance FROM MinecraftServers WHERE name=$1",
+                &[&minecraft_server],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.map(|r| minecraft_server_from_row(&r))),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Minecraft server".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    // Only writes to the database on the first login request for a server
+    pub async fn get_or_add_minecraft_server(
+        &self,
+        minecraft_server: &str,
+    ) -> Result<MinecraftServer, DatabaseError> {
+        if let Some(server) = self.get_minecraft_server(minecraft_server).await? {
+            return Ok(server);
+        }
+
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "INSERT INTO MinecraftServers(name) VALUES($1) ON CONFLICT (name) DO UPDATE SET name=EXCLUDED.name RETURNING name, requiredroleid, sessionminutes, requireapproval, maintenance",
+                &[&minecraft_server],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(minecraft_server_from_row(&r)),
+            Err(e) => Err(DatabaseError::InsertError {
+                data: "Minecraft server".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn set_minecraft_server(&self, server: &MinecraftServer) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "INSERT INTO MinecraftServers(name, requiredroleid, sessionminutes, requireapproval, maintenance) VALUES($1, $2, $3, $4, $5)
+                 ON CONFLICT (name) DO UPDATE SET requiredroleid=EXCLUDED.requiredroleid, sessionminutes=EXCLUDED.sessionminutes, requireapproval=EXCLUDED.requireapproval, maintenance=EXCLUDED.maintenance",
+                &[
+                    &server.name,
+                    &server.required_role_id,
+                    &server.session_minutes,
+                    &server.require_approval,
+                    &server.maintenance,
+                ],
+            )
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::UpdateError {
+                data: "Minecraft server".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    // Tells the Minecraft server to disconnect the player, the payload is the Minecraft user name
+    pub async fn notify_revoked_auth(&self, minecraft_user_id: &str) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "SELECT pg_notify('revoked_auths', $1)",
+                &[&minecraft_user_id],
+            )
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::NotifyError {
+                data: "revoked authentication".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_player_session(
+        &self,
+        discord_id: &str,
+    ) -> Result<Option<PlayerSession>, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "SELECT CAST(EXTRACT(EPOCH FROM CAST(expiration AS TIMESTAMPTZ)) AS BIGINT) AS expiration, ipaddress FROM AuthenticatedPlayers WHERE discordname=$1",
+                &[&discord_id],
+            )
+            .await;
+
+        match row {
+            Ok(Some(r)) => Ok(Some(PlayerSession {
+                expiration: r.get("expiration"),
+                ip_address: r.get("ipaddress"),
+            })),
+            Ok(None) => Ok(None),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Player session".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_recent_authentication_requests(
+        &self,
+        minecraft_user_id: &str,
+        minecraft_server: Option<&str>,
+        limit: &i64,
+    ) -> Result<Vec<AuthenticationRequest>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT minecraftserver, ipaddress, state, CAST(EXTRACT(EPOCH FROM CAST(created AS TIMESTAMPTZ)) AS BIGINT) AS created FROM AuthenticationRequests WHERE minecraftname=$1 AND ($2::TEXT IS NULL OR minecraftserver=$2) ORDER BY created DESC LIMIT $3",
+                &[&minecraft_user_id, &minecraft_server, &limit],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => rows
+                .iter()
+                .map(|r| {
+                    let state: String = r.get("state");
+                    match AuthenticationState::parse(&state) {
+                        Some(state) => Ok(AuthenticationRequest {
+                            minecraft_server: r.get("minecraftserver"),
+                            ip_address: r.get("ipaddress"),
+                            state,
+                            created: r.get("created"),
+                        }),
+                        None => Err(DatabaseError::SelectError {
+                            data: "Authentication request".to_string(),
+                            why: format!("Unknown state '{}'", state),
+                        }),
+                    }
+                })
+                .collect(),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Authentication request".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    // Counts the login prompts sent for the Minecraft user and from the IP address within the window
+    pub async fn count_recent_prompts(
+        &self,
+        minecraft_user_id: &str,
+        ip_address: &str,
+        window_seconds: &i32,
+    ) -> Result<(i64, i64), DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "SELECT COUNT(*) FILTER (WHERE AuthenticationRequests.minecraftname=$1) AS player_prompts, COUNT(*) FILTER (WHERE AuthenticationRequests.ipaddress=$2) AS ip_prompts FROM AuthenticationRequestStates INNER JOIN AuthenticationRequests ON (AuthenticationRequestStates.authrequestid=AuthenticationRequests.id) WHERE AuthenticationRequestStates.state='prompted' AND AuthenticationRequestStates.created >= now()::timestamp - ($3::INT * INTERVAL '1 second')",
+                &[&minecraft_user_id, &ip_address, &window_seconds],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok((r.get("player_prompts"), r.get("ip_prompts"))),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Authentication request states".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn count_recent_throttled(
+        &self,
+        minecraft_user_id: &str,
+        window_seconds: &i32,
+    ) -> Result<i64, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "SELECT COUNT(*) FROM AuthenticationRequests WHERE minecraftname=$1 AND state='throttled' AND statechanged >= now()::timestamp - ($2::INT * INTERVAL '1 second')",
+                &[&minecraft_user_id, &window_seconds],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.get::<usize, i64>(0)),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Authentication request".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_unhandled_authentication_requests(
+        &self,
+    ) -> Result<Vec<UnhandledAuthenticationRequest>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT id, minecraftserver, state, CAST(EXTRACT(EPOCH FROM now()::timestamp - statechanged) AS BIGINT) AS stateage FROM AuthenticationRequests WHERE handled = FALSE ORDER BY id",
+                &[],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => rows
+                .iter()
+                .map(|r| {
+                    let state: String = r.get("state");
+                    match AuthenticationState::parse(&state) {
+                        Some(state) => Ok(UnhandledAuthenticationRequest {
+                            id: r.get("id"),
+                            minecraft_server: r.get("minecraftserver"),
+                            state,
+                            state_age: r.get("stateage"),
+                        }),
+                        None => Err(DatabaseError::SelectError {
+                            data: "Authentication request".to_string(),
+                            why: format!("Unknown state '{}'", state),
+                        }),
+                    }
+                })
+                .collect(),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Authentication request".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn is_authentication_request_handled(
+        &self,
+        request_id: &i32,
+    ) -> Result<bool, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "SELECT handled FROM AuthenticationRequests WHERE id=$1",
+                &[&request_id],
+            )
+            .await;
+
+        match row {
+            Ok(Some(r)) => Ok(r.get::<&str, bool>("handled")),
+            Ok(None) => Err(DatabaseError::MissingAuthenticationRequest(
+                request_id.to_string(),
+            )),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Authentication request".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn set_authentication_state(
+        &self,
+        request_id: &i32,
+        state: &AuthenticationState,
+        discord_message_id: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "UPDATE AuthenticationRequests SET state=$2, discordmessageid=COALESCE($3, discordmessageid), statechanged=now()::timestamp, handled=$4 WHERE id=$1",
+                &[&request_id, &state.as_str(), &discord_message_id, &state.is_resolved()],
+            )
+            .await;
+
+        match result {
+            Ok(0) => Err(DatabaseError::MissingAuthenticationRequest(
+                request_id.to_string(),
+            )),
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::UpdateError {
+                data: "Authentication request state".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn set_player_locked(&self, discord_id: &str, locked: &bool) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "UPDATE Players SET locked=$2 WHERE discordname=$1",
+                &[&discord_id, &locked],
+            )
+            .await;
+
+        match result {
+            Ok(0) => Err(DatabaseError::PlayerNotRegistered(discord_id.to_string())),
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::UpdateError {
+                data: "Player lock".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_minecraft_users_starting_with(
+        &self,
+        prefix: &str,
+        limit: &i64,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT minecraftname FROM Players WHERE starts_with(lower(minecraftname), lower($1)) ORDER BY minecraftname LIMIT $2",
+                &[&prefix, &limit],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => Ok(rows.iter().map(|r| r.get("minecraftname")).collect()),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Minecraft user names".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    // Servers are known once a login request has been made from them
+    pub async fn get_minecraft_servers_starting_with(
+        &self,
+        prefix: &str,
+        limit: &i64,
+    ) -> Result<Vec<String>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT name FROM (SELECT name FROM MinecraftServers UNION SELECT minecraftserver FROM AuthenticationRequests) AS Servers WHERE starts_with(lower(name), lower($1)) ORDER BY name LIMIT $2",
+                &[&prefix, &limit],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => Ok(rows.iter().map(|r| r.get("name")).collect()),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Minecraft servers".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_pending_registrations(&self) -> Result<Vec<String>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT discordname FROM Players WHERE minecraftname IS NULL ORDER BY discordname",
+                &[],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => Ok(rows.iter().map(|r| r.get("discordname")).collect()),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Pending registrations".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn set_minecraft_user(&self, discord_id: &str, minecraft_user_id: &str) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "UPDATE Players SET minecraftname=$2 WHERE discordname=$1",
+                &[&discord_id, &minecraft_user_id],
+            )
+            .await;
+
+        match result {
+            Ok(0) => Err(DatabaseError::PlayerNotRegistered(discord_id.to_string())),
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::UpdateError {
+                data: "Minecraft user name".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn set_reg_code(
+        &self,
+        discord_id: &str,
+        reg_code: &str,
+        code_ttl_minutes: &i32,
+    ) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = connection
+            .execute(
+                "UPDATE Players SET registrationcode=HashRegistrationCode($2), registrationcodecreated=now()::timestamp, registrationcodeexpiration=now()::timestamp + ($3::INT * INTERVAL '1 minute') WHERE discordname=$1",
+                &[&discord_id, &reg_code, &code_ttl_minutes],
+            )
+            .await;
+
+        match result {
+            Ok(0) => Err(DatabaseError::PlayerNotRegistered(discord_id.to_string())),
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_registration_code(&e) => Err(DatabaseError::DuplicateRegistrationCode),
+            Err(e) => Err(DatabaseError::UpdateError {
+                data: "Registration code".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_trusted_addresses(
+        &self,
+        discord_id: &str,
+    ) -> Result<Vec<TrustedAddress>, DatabaseError> {
+        let connection = self.connection().await?;
+        let rows = connection
+            .query(
+                "SELECT ipaddress, CAST(EXTRACT(EPOCH FROM CAST(expiration AS TIMESTAMPTZ)) AS BIGINT) AS expiration FROM TrustedAddresses WHERE discordname=$1 AND expiration >= now()::timestamp ORDER BY created DESC",
+                &[&discord_id],
+            )
+            .await;
+
+        match rows {
+            Ok(rows) => Ok(rows
+                .iter()
+                .map(|r| TrustedAddress {
+                    ip_address: r.get("ipaddress"),
+                    expiration: r.get("expiration"),
+                })
+                .collect()),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Trusted address".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn is_trusted_address(
+        &self,
+        discord_id: &str,
+        ip_address: &str,
+    ) -> Result<bool, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_one(
+                "SELECT COUNT(*) FROM TrustedAddresses WHERE discordname=$1 AND ipaddress=$2 AND expiration >= now()::timestamp",
+                &[&discord_id, &ip_address],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.get::<usize, i64>(0) > 0),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Trusted address".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
     pub async fn is_player_authenticated(
         &self,
         discord_id: &str,
         ip_address: &str,
     ) -> Result<bool, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let row =
             connection
             .query_one(
@@ -328,9 +1103,76 @@ impl Database {
         }
     }
 
+    pub async fn is_player_locked(&self, discord_id: &str) -> Result<bool, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "SELECT locked FROM Players WHERE discordname=$1",
+                &[&discord_id],
+            )
+            .await;
+
+        match row {
+            Ok(Some(r)) => Ok(r.get("locked")),
+            Ok(None) => Err(DatabaseError::PlayerNotRegistered(discord_id.to_string())),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Player lock".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    pub async fn get_locale(&self, discord_id: &str) -> Result<Option<String>, DatabaseError> {
+        let connection = self.connection().await?;
+        let row = connection
+            .query_opt(
+                "SELECT locale FROM UserPreferences WHERE discordname=$1",
+                &[&discord_id],
+            )
+            .await;
+
+        match row {
+            Ok(r) => Ok(r.map(|r| r.get("locale"))),
+            Err(e) => Err(DatabaseError::SelectError {
+                data: "Locale".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
+    // Without a locale the language of the user's Discord client is used again
+    pub async fn set_locale(&self, discord_id: &str, locale: Option<&str>) -> Result<(), DatabaseError> {
+        let connection = self.connection().await?;
+        let result = match locale {
+            Some(locale) => {
+                connection
+                    .execute(
+                        "INSERT INTO UserPreferences(discordname, locale) VALUES($1, $2) ON CONFLICT (discordname) DO UPDATE SET locale=EXCLUDED.locale",
+                        &[&discord_id, &locale],
+                    )
+                    .await
+            }
+            None => {
+                connection
+                    .execute(
+                        "DELETE FROM UserPreferences WHERE discordname=$1",
+                        &[&discord_id],
+                    )
+                    .await
+            }
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(DatabaseError::UpdateError {
+                data: "Locale".to_string(),
+                why: e.to_string(),
+            }),
+        }
+    }
+
     pub async fn is_player_registered(&self, discord_id: &str) -> Result<bool, DatabaseError> {
-        let pool = Arc::clone(&self.pool);
-        let connection = pool.get().await.unwrap();
+        let connection = self.connection().await?;
         let row = connection
             .query_one(
                 "SELECT COUNT(*) FROM Players WHERE discordname=$1",
@@ -350,6 +1192,16 @@ impl Database {
         }
     }
 
+    // Fails instead of panicking when the pool has no connection available within its connection timeout
+    async fn connection(
+        &self,
+    ) -> Result<PooledConnection<'_, PostgresConnectionManager<NoTls>>, DatabaseError> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| DatabaseError::ConnectionError(e.to_string()))
+    }
+
     pub async fn new(
         pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
     ) -> Result<Database, Box<dyn Error>> {
@@ -359,18 +1211,120 @@ impl Database {
     }
 }
 
+fn minecraft_server_from_row(row: &tokio_postgres::Row) -> MinecraftServer {
+    MinecraftServer {
+        name: row.get("name"),
+        required_role_id: row.get("requiredroleid"),
+        session_minutes: row.get("sessionminutes"),
+        require_approval: row.get("requireapproval"),
+        maintenance: row.get("maintenance"),
+    }
+}
+
+fn is_duplicate_registration_code(error: &tokio_postgres::Error) -> bool {
+    error
+        .as_db_error()
+        .and_then(|e| e.constraint())
+        == Some("players_registrationcode_key")
+}
+
 pub async fn get_connection_pool(
-    connection_string: &str,
+    settings: &PostgresSettings,
 ) -> Pool<PostgresConnectionManager<NoTls>> {
     let manager = bb8_postgres::PostgresConnectionManager::new(
-        Config::from_str(connection_string).unwrap(),
+        Config::from_str(&settings.url).unwrap(),
         NoTls,
     );
     let pool = bb8::Pool::builder()
-        .max_size(20)
+        .max_size(settings.pool_size)
+        .connection_timeout(Duration::from_secs(settings.connection_timeout_seconds))
         .build(manager)
         .await
         .unwrap();
 
     return pool;
 }
+
+#[cfg(test)]
+pub mod test_database {
+    use super::*;
+    use crate::services::migrations;
+    use rand::{distributions::Alphanumeric, Rng};
+    use tokio_postgres::Client;
+
+    fn config(connection_string: &str, schema: &str) -> Config {
+        let mut config = Config::from_str(connection_string).unwrap();
+        config.options(&format!("-c search_path={}", schema));
+        config
+    }
+
+    // An isolated schema with the bot's tables in the Postgres database pointed to by POSTGRES_URL
+    pub struct TestDatabase {
+        pub pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        connection_string: String,
+        schema: String,
+    }
+
+    impl TestDatabase {
+        pub async fn new() -> TestDatabase {
+            let database = TestDatabase::empty().await;
+            migrations::migrate(&mut database.client().await, false).await.unwrap();
+
+            database
+        }
+
+        // Without any tables, for testing the migrations themselves
+        pub async fn empty() -> TestDatabase {
+            let connection_string =
+                std::env::var("POSTGRES_URL").expect("Could not retrieve POSTGRES_URL");
+            let schema = format!(
+                "test_{}",
+                rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(12)
+                    .map(char::from)
+                    .collect::<String>()
+                    .to_lowercase()
+            );
+
+            let (client, connection) = tokio_postgres::connect(&connection_string, NoTls)
+                .await
+                .unwrap();
+            tokio::spawn(connection);
+            client
+                .batch_execute(&format!("CREATE SCHEMA {}", schema))
+                .await
+                .unwrap();
+
+            let manager = PostgresConnectionManager::new(config(&connection_string, &schema), NoTls);
+            let pool = bb8::Pool::builder().build(manager).await.unwrap();
+
+            TestDatabase {
+                pool: Arc::new(pool),
+                connection_string,
+                schema,
+            }
+        }
+
+        pub async fn client(&self) -> Client {
+            let (client, connection) = config(&self.connection_string, &self.schema)
+                .connect(NoTls)
+                .await
+                .unwrap();
+            tokio::spawn(connection);
+
+            client
+        }
+
+        pub async fn drop(self) {
+            let (client, connection) = tokio_postgres::connect(&self.connection_string, NoTls)
+                .await
+                .unwrap();
+            tokio::spawn(connection);
+            client
+                .batch_execute(&format!("DROP SCHEMA {} CASCADE", self.schema))
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -1,8 +1,189 @@
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::Timestamp;
 use serenity::utils::Colour;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct EmbedData {
     pub title: Option<String>,
     pub description: Option<String>,
     pub colour: Option<Colour>,
+    pub author: Option<String>,
+    // URL of a small image in the top right corner of the embed
+    pub thumbnail: Option<String>,
+    pub fields: Vec<EmbedField>,
+    pub footer: Option<String>,
+    pub timestamp: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum ButtonAction {
+    // Sent back to the bot as a component interaction when the button is clicked
+    CustomId(String),
+    // Opens the link in the browser of the user
+    Url(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct ButtonData {
+    pub label: String,
+    pub style: ButtonStyle,
+    pub action: ButtonAction,
+}
+
+// A message made up of one or more embeds, optionally followed by rows of buttons
+#[derive(Debug, Clone, Default)]
+pub struct ResponseData {
+    pub embeds: Vec<EmbedData>,
+    pub action_rows: Vec<Vec<ButtonData>>,
+}
+
+impl EmbedData {
+    pub fn field(mut self, name: impl ToString, value: impl ToString, inline: bool) -> EmbedData {
+        self.fields.push(EmbedField {
+            name: name.to_string(),
+            value: value.to_string(),
+            inline,
+        });
+        self
+    }
+
+    pub fn create_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::default();
+
+        if let Some(title) = &self.title {
+            embed.title(title);
+        }
+
+        if let Some(description) = &self.description {
+            embed.description(description);
+        }
+
+        if let Some(colour) = self.colour {
+            embed.colour(colour);
+        }
+
+        if let Some(author) = &self.author {
+            embed.author(|a| a.name(author));
+        }
+
+        if let Some(thumbnail) = &self.thumbnail {
+            embed.thumbnail(thumbnail);
+        }
+
+        for field in self.fields.iter() {
+            embed.field(&field.name, &field.value, field.inline);
+        }
+
+        if let Some(footer) = &self.footer {
+            embed.footer(|f| f.text(footer));
+        }
+
+        if let Some(timestamp) = self.timestamp {
+            embed.timestamp(timestamp);
+        }
+
+        embed
+    }
+}
+
+impl ResponseData {
+    pub fn create_embeds(&self) -> Vec<CreateEmbed> {
+        self.embeds.iter().map(|e| e.create_embed()).collect()
+    }
+
+    // An empty result removes the buttons when used to edit a message
+    pub fn create_components(&self) -> CreateComponents {
+        let mut components = CreateComponents::default();
+
+        for buttons in self.action_rows.iter() {
+            components.create_action_row(|row| {
+                for button in buttons.iter() {
+                    row.create_button(|b| {
+                        b.label(&button.label).style(button.style);
+
+                        match &button.action {
+                            ButtonAction::CustomId(custom_id) => b.custom_id(custom_id),
+                            ButtonAction::Url(url) => b.url(url),
+                        }
+                    });
+                }
+
+                row
+            });
+        }
+
+        components
+    }
+}
+
+impl From<EmbedData> for ResponseData {
+    fn from(embed: EmbedData) -> ResponseData {
+        ResponseData {
+            embeds: vec![embed],
+            action_rows: Vec::new(),
+        }
+    }
+}
+
+pub fn minecraft_avatar_url(minecraft_user: &str) -> String {
+    format!("https://mc-heads.net/avatar/{}", minecraft_user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_embed_with_fields_and_footer() {
+        let embed = EmbedData {
+            title: Some("Minecraft status".to_string()),
+            footer: Some("Footer".to_string()),
+            thumbnail: Some(minecraft_avatar_url("Steve")),
+            ..Default::default()
+        }
+        .field("Registered", "yes", true)
+        .create_embed();
+
+        assert_eq!(embed.0["title"], json!("Minecraft status"));
+        assert_eq!(embed.0["fields"], json!([{ "name": "Registered", "value": "yes", "inline": true }]));
+        assert_eq!(embed.0["footer"], json!({ "text": "Footer" }));
+        assert_eq!(embed.0["thumbnail"], json!({ "url": "https://mc-heads.net/avatar/Steve" }));
+        assert!(!embed.0.contains_key("description"));
+    }
+
+    #[test]
+    fn renders_one_action_row_per_button_row() {
+        let response = ResponseData {
+            embeds: vec![EmbedData::default(), EmbedData::default()],
+            action_rows: vec![
+                vec![ButtonData {
+                    label: "Approve".to_string(),
+                    style: ButtonStyle::Success,
+                    action: ButtonAction::CustomId("auth:approve:1".to_string()),
+                }],
+                vec![ButtonData {
+                    label: "Help".to_string(),
+                    style: ButtonStyle::Link,
+                    action: ButtonAction::Url("https://example.com".to_string()),
+                }],
+            ],
+        };
+
+        assert_eq!(response.create_embeds().len(), 2);
+
+        let components = response.create_components();
+        assert_eq!(components.0.len(), 2);
+        assert_eq!(components.0[0]["components"][0]["custom_id"], json!("auth:approve:1"));
+        assert_eq!(components.0[1]["components"][0]["url"], json!("https://example.com"));
+        assert!(ResponseData::default().create_components().0.is_empty());
+    }
 }
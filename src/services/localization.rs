@@ -0,0 +1,538 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use super::settings::LocalizationSettings;
+
+// Every other bundle is checked against this one. Its command names and descriptions are the ones
+// declared by the commands themselves, other bundles can translate them under [commands].
+static BASE_LANGUAGE: &str = "en";
+// The bundles are compiled into the binary
+static BUNDLES: &[(&str, &str, &str)] = &[
+    ("en", "en-US", include_str!("../../locales/en.toml")),
+    ("sv", "sv-SE", include_str!("../../locales/sv.toml")),
+];
+static COMMANDS_PREFIX: &str = "commands.";
+static MESSAGE_PARAMETERS: &[MessageParameters] = &[
+    ("duration.second", &["count"]),
+    ("duration.seconds", &["count"]),
+    ("duration.minute", &["count"]),
+    ("duration.minutes", &["count"]),
+    ("duration.hour", &["count"]),
+    ("duration.hours", &["count"]),
+];
+
+// A message and the variables the code passes to it, declared next to the code that renders the message.
+// Messages that are not declared are rendered without any.
+pub type MessageParameters = (&'static str, &'static [&'static str]);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LocalizationError {
+    #[error("There is no message bundle for '{0}'")]
+    UnknownLanguage(String),
+    #[error("The messages in {file} are invalid (Error: {why})")]
+    InvalidBundle { file: String, why: String },
+    #[error("The message '{id}' is missing in the bundle for '{language}'")]
+    MissingMessage { language: String, id: String },
+    #[error("The message '{id}' does not exist, but is replaced by the templates in {file}")]
+    UnknownMessage { file: String, id: String },
+    #[error("Could not read the templates in {path} (Error: {why})")]
+    UnreadableTemplates { path: String, why: String },
+    #[error("The message '{id}' in {file} uses the variable '{variable}', which is neither passed to the message nor configured")]
+    UnknownVariable {
+        file: String,
+        id: String,
+        variable: String,
+    },
+}
+
+struct Bundle {
+    language: &'static str,
+    discord_locale: &'static str,
+    messages: HashMap<String, String>,
+    // The templates file of the messages that were replaced by templates, for reporting errors
+    templates: HashMap<String, String>,
+}
+
+impl Bundle {
+    // Where the message comes from, either the bundled messages or a templates file
+    fn file(&self, id: &str) -> String {
+        match self.templates.get(id) {
+            Some(file) => file.clone(),
+            None => bundle_file(self.language),
+        }
+    }
+}
+
+pub struct Catalog {
+    bundles: Vec<Bundle>,
+    // Used for locales without a bundle and when the locale of a user is not known
+    default_language: &'static str,
+    variables: HashMap<String, String>,
+}
+
+impl Catalog {
+    // Every bundle must contain the messages of the base bundle, using no other variables than the ones
+    // in `parameters` and the configured ones
+    pub fn load(settings: &LocalizationSettings, parameters: &[MessageParameters]) -> Result<Catalog, LocalizationError> {
+        let mut bundles = Vec::new();
+
+        for (language, discord_locale, source) in BUNDLES {
+            let file = bundle_file(language);
+            let mut messages = HashMap::new();
+            flatten(&file, "", &parse(&file, source)?, &mut messages)?;
+
+            bundles.push(Bundle {
+                language,
+                discord_locale,
+                messages,
+                templates: HashMap::new(),
+            });
+        }
+
+        let mut catalog = Catalog {
+            bundles,
+            default_language: BASE_LANGUAGE,
+            variables: settings.variables.clone(),
+        };
+        let parameters = catalog.parameters(parameters)?;
+        catalog.validate_bundles()?;
+
+        if let Some(directory) = &settings.templates_directory {
+            catalog.add_templates(Path::new(directory))?;
+        }
+
+        catalog.validate_variables(&parameters)?;
+        catalog.default_language = catalog
+            .find_language(&settings.default_locale)
+            .ok_or_else(|| LocalizationError::UnknownLanguage(settings.default_locale.clone()))?;
+
+        Ok(catalog)
+    }
+
+    // Rejects declarations of messages that do not exist, which would leave their messages unchecked
+    fn parameters<'a>(
+        &self,
+        declared: &'a [MessageParameters],
+    ) -> Result<HashMap<&'a str, HashSet<&'a str>>, LocalizationError> {
+        let base = self.bundle(BASE_LANGUAGE);
+
+        MESSAGE_PARAMETERS
+            .iter()
+            .chain(declared.iter())
+            .map(|(id, parameters)| {
+                if !base.messages.contains_key(*id) {
+                    return Err(LocalizationError::MissingMessage {
+                        language: BASE_LANGUAGE.to_string(),
+                        id: id.to_string(),
+                    });
+                }
+
+                Ok((*id, parameters.iter().copied().collect()))
+            })
+            .collect()
+    }
+
+    fn validate_bundles(&self) -> Result<(), LocalizationError> {
+        let default = self.bundle(BASE_LANGUAGE);
+
+        for bundle in self.bundles.iter() {
+            if let Some(id) = default.messages.keys().find(|id| !bundle.messages.contains_key(*id)) {
+                return Err(LocalizationError::MissingMessage {
+                    language: bundle.language.to_string(),
+                    id: id.clone(),
+                });
+            }
+
+            if let Some(id) = bundle
+                .messages
+                .keys()
+                .find(|id| !id.starts_with(COMMANDS_PREFIX) && !default.messages.contains_key(*id))
+            {
+                return Err(LocalizationError::MissingMessage {
+                    language: BASE_LANGUAGE.to_string(),
+                    id: id.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    // Templates are the bundled messages of a language with some of them replaced
+    fn add_templates(&mut self, directory: &Path) -> Result<(), LocalizationError> {
+        for bundle in self.bundles.iter_mut() {
+            let path = directory.join(format!("{}.toml", bundle.language));
+
+            if !path.exists() {
+                continue;
+            }
+
+            let file = path.display().to_string();
+            let source = fs::read_to_string(&path).map_err(|e| LocalizationError::UnreadableTemplates {
+                path: file.clone(),
+                why: e.to_string(),
+            })?;
+            let mut templates = HashMap::new();
+            flatten(&file, "", &parse(&file, &source)?, &mut templates)?;
+
+            for (id, template) in templates {
+                if !id.starts_with(COMMANDS_PREFIX) && !bundle.messages.contains_key(&id) {
+                    return Err(LocalizationError::UnknownMessage { file, id });
+                }
+
+                bundle.templates.insert(id.clone(), file.clone());
+                bundle.messages.insert(id, template);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_variables(&self, parameters: &HashMap<&str, HashSet<&str>>) -> Result<(), LocalizationError> {
+        let none = HashSet::new();
+
+        for bundle in self.bundles.iter() {
+            for (id, message) in bundle.messages.iter() {
+                let parameters = parameters.get(id.as_str()).unwrap_or(&none);
+
+                if let Some(variable) = variables(message)
+                    .into_iter()
+                    .find(|v| !parameters.contains(*v) && !self.variables.contains_key(*v))
+                {
+                    return Err(LocalizationError::UnknownVariable {
+                        file: bundle.file(id),
+                        id: id.clone(),
+                        variable: variable.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn bundle(&self, language: &str) -> &Bundle {
+        self.bundles
+            .iter()
+            .find(|b| b.language == language)
+            .unwrap_or(&self.bundles[0])
+    }
+
+    fn find_language(&self, locale: &str) -> Option<&'static str> {
+        let language = locale.split('-').next().unwrap_or(locale);
+
+        self.bundles
+            .iter()
+            .find(|b| b.language.eq_ignore_ascii_case(language))
+            .map(|b| b.language)
+    }
+
+    // Maps a Discord locale such as sv-SE or en-GB to one of the bundled languages
+    pub fn language(&self, locale: &str) -> &'static str {
+        self.find_language(locale).unwrap_or(self.default_language)
+    }
+
+    pub fn default_language(&self) -> &'static str {
+        self.default_language
+    }
+
+    pub fn languages(&self) -> Vec<&'static str> {
+        self.bundles.iter().map(|b| b.language).collect()
+    }
+
+    // Variables are written as {name} in the messages and replaced by the values in `args` or the
+    // configured variables. The values are inserted as they are, even if they contain a {name} themselves.
+    pub fn message(&self, locale: &str, id: &str, args: &[(&str, &str)]) -> String {
+        let message = match self.bundle(self.language(locale)).messages.get(id) {
+            Some(message) => message,
+            None => {
+                println!("Could not find the message '{}'", id);
+                return id.to_string();
+            }
+        };
+
+        render(message, |name| {
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| *value)
+                .or_else(|| self.variables.get(name).map(String::as_str))
+        })
+    }
+
+    pub fn duration(&self, locale: &str, duration: Duration) -> String {
+        let seconds = duration.as_secs();
+        let hours = seconds / 3600;
+        let (value, unit) = if seconds < 60 {
+            (seconds, "second")
+        } else if hours == 0 || hours * 3600 != seconds {
+            (seconds / 60, "minute")
+        } else {
+            (hours, "hour")
+        };
+        let id = match value {
+            1 => format!("duration.{}", unit),
+            _ => format!("duration.{}s", unit),
+        };
+
+        self.message(locale, &id, &[("count", &value.to_string())])
+    }
+
+    // Translations of a command name or description for Discord's localization maps, keyed by Discord locale
+    pub fn command_translations(&self, path: &str) -> Vec<(&'static str, &str)> {
+        let id = format!("{}{}", COMMANDS_PREFIX, path);
+
+        self.bundles
+            .iter()
+            .filter(|b| b.language != BASE_LANGUAGE)
+            .filter_map(|b| b.messages.get(&id).map(|m| (b.discord_locale, m.as_str())))
+            .collect()
+    }
+}
+
+fn bundle_file(language: &str) -> String {
+    format!("locales/{}.toml", language)
+}
+
+fn parse(file: &str, source: &str) -> Result<toml::Value, LocalizationError> {
+    source
+        .parse::<toml::Value>()
+        .map_err(|e| LocalizationError::InvalidBundle {
+            file: file.to_string(),
+            why: e.to_string(),
+        })
+}
+
+fn flatten(
+    file: &str,
+    prefix: &str,
+    value: &toml::Value,
+    messages: &mut HashMap<String, String>,
+) -> Result<(), LocalizationError> {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter() {
+                let id = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(file, &id, value, messages)?;
+            }
+
+            Ok(())
+        }
+        toml::Value::String(message) => {
+            messages.insert(prefix.to_string(), message.clone());
+            Ok(())
+        }
+        _ => Err(LocalizationError::InvalidBundle {
+            file: file.to_string(),
+            why: format!("'{}' is not a string", prefix),
+        }),
+    }
+}
+
+fn render<'a>(message: &str, value: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut rendered = String::with_capacity(message.len());
+    let mut rest = message;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        match rest.split_once('}').and_then(|(name, after)| value(name).map(|value| (value, after))) {
+            Some((value, after)) => {
+                rendered.push_str(value);
+                rest = after;
+            }
+            None => rendered.push('{'),
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+fn variables(message: &str) -> HashSet<&str> {
+    message
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(variable, _)| variable))
+        .filter(|variable| !variable.is_empty() && variable.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test_catalog {
+    use super::*;
+
+    pub fn settings(default_locale: &str) -> LocalizationSettings {
+        LocalizationSettings {
+            default_locale: default_locale.to_string(),
+            templates_directory: None,
+            variables: HashMap::from([
+                ("server_address".to_string(), "minecraft.example.com".to_string()),
+                ("support_contact".to_string(), "the server admins".to_string()),
+            ]),
+        }
+    }
+
+    pub fn load(settings: &LocalizationSettings) -> Result<Catalog, LocalizationError> {
+        Catalog::load(settings, &crate::bot::message_parameters())
+    }
+
+    pub fn catalog() -> Catalog {
+        load(&settings("en")).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_catalog::{catalog, load, settings};
+    use super::*;
+    use rand::{distributions::Alphanumeric, Rng};
+
+    // A directory with the given <language>.toml templates, removed by the caller
+    fn templates(files: &[(&str, &str)]) -> String {
+        let name: String = rand::thread_rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect();
+        let directory = std::env::temp_dir().join(format!("templates_{}", name));
+        fs::create_dir(&directory).unwrap();
+
+        for (language, source) in files {
+            fs::write(directory.join(format!("{}.toml", language)), source).unwrap();
+        }
+
+        directory.display().to_string()
+    }
+
+    #[test]
+    fn bundled_languages_are_complete() {
+        assert_eq!(catalog().languages(), vec!["en", "sv"]);
+    }
+
+    #[test]
+    fn resolves_discord_locales() {
+        let catalog = catalog();
+
+        assert_eq!(catalog.language("sv-SE"), "sv");
+        assert_eq!(catalog.language("en-GB"), "en");
+        assert_eq!(catalog.language("de"), "en");
+        assert_eq!(load(&settings("sv-SE")).unwrap().language("de"), "sv");
+        assert_eq!(
+            load(&settings("de")).err(),
+            Some(LocalizationError::UnknownLanguage("de".to_string()))
+        );
+    }
+
+    #[test]
+    fn replaces_variables_and_falls_back_to_id() {
+        let catalog = catalog();
+
+        assert_eq!(
+            catalog.message("en-US", "login.trust", &[("days", "30")]),
+            "Trust this network for 30 days"
+        );
+        assert_eq!(
+            catalog.message("en-US", "register.server-address", &[]),
+            "```\nminecraft.example.com\n```"
+        );
+        assert_eq!(
+            catalog.message("en-US", "trusted.revoked", &[("ip_address", "{server_address}")]),
+            "The network `{server_address}` is no longer trusted."
+        );
+        assert_eq!(catalog.message("sv-SE", "no.such.message", &[]), "no.such.message");
+        assert_eq!(catalog.duration("sv-SE", Duration::from_secs(120)), "2 minuter");
+        assert_eq!(catalog.duration("en-US", Duration::from_secs(3600)), "1 hour");
+    }
+
+    #[test]
+    fn requires_configured_variables() {
+        let mut settings = settings("en");
+        settings.variables.remove("server_address");
+
+        assert!(matches!(
+            load(&settings).err(),
+            Some(LocalizationError::UnknownVariable { variable, .. }) if variable == "server_address"
+        ));
+    }
+
+    #[test]
+    fn templates_replace_bundled_messages() {
+        let directory = templates(&[("sv", "[login]\ndeny = \"Nej, {support_contact} {discord_invite}\"")]);
+        let mut settings = settings("en");
+        settings.templates_directory = Some(directory.clone());
+        settings.variables.insert("discord_invite".to_string(), "discord.gg/example".to_string());
+
+        let catalog = load(&settings);
+        fs::remove_dir_all(&directory).unwrap();
+        let catalog = catalog.unwrap();
+
+        assert_eq!(catalog.message("sv", "login.deny", &[]), "Nej, the server admins discord.gg/example");
+        assert_eq!(catalog.message("en", "login.deny", &[]), "Deny");
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        // The error for the templates file, whose path is only known once it has been written
+        type Expected = fn(String) -> LocalizationError;
+
+        let cases: [(&str, Expected); 3] = [
+            (
+                "[login]\ndenied = \"Denied {minecraft_user}\"",
+                |file| LocalizationError::UnknownVariable {
+                    file,
+                    id: "login.denied".to_string(),
+                    variable: "minecraft_user".to_string(),
+                },
+            ),
+            (
+                "[login]\nunknown = \"Hello\"",
+                |file| LocalizationError::UnknownMessage {
+                    file,
+                    id: "login.unknown".to_string(),
+                },
+            ),
+            (
+                "[login]\ndenied = 4",
+                |file| LocalizationError::InvalidBundle {
+                    file,
+                    why: "'login.denied' is not a string".to_string(),
+                },
+            ),
+        ];
+
+        for (source, expected) in cases {
+            let directory = templates(&[("en", source)]);
+            let mut settings = settings("en");
+            settings.templates_directory = Some(directory.clone());
+
+            let catalog = load(&settings);
+            fs::remove_dir_all(&directory).unwrap();
+
+            let file = Path::new(&directory).join("en.toml").display().to_string();
+            assert_eq!(catalog.err(), Some(expected(file)));
+        }
+    }
+
+    #[test]
+    fn rejects_parameters_of_unknown_messages() {
+        assert_eq!(
+            Catalog::load(&settings("en"), &[("login.unknown", &["minecraft_user"])]).err(),
+            Some(LocalizationError::MissingMessage {
+                language: "en".to_string(),
+                id: "login.unknown".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn finds_variables() {
+        let variables = variables("{code} expires <t:{expiration}:R> ```{ }```");
+
+        assert_eq!(variables, HashSet::from(["code", "expiration"]));
+    }
+}
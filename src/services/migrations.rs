@@ -0,0 +1,253 @@
+use std::collections::HashSet;
+
+use thiserror::Error;
+use tokio_postgres::Client;
+
+// Held while migrating so that two instances of the bot do not migrate the same database at once
+static LOCK_ID: i64 = 7_130_517_221;
+static CREATE_VERSIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS SchemaMigrations (
+       version INT PRIMARY KEY,
+       name TEXT NOT NULL,
+       applied TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+// Applied in order of their version, which may never change once a migration has been released
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "baseline",
+        sql: include_str!("../../database/migrations/0001_baseline.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "registration codes",
+        sql: include_str!("../../database/migrations/0002_registration_codes.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "authentication request states",
+        sql: include_str!("../../database/migrations/0003_authentication_request_states.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "account protection",
+        sql: include_str!("../../database/migrations/0004_account_protection.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "minecraft servers",
+        sql: include_str!("../../database/migrations/0005_minecraft_servers.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "user preferences",
+        sql: include_str!("../../database/migrations/0006_user_preferences.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "short code checksums",
+        sql: include_str!("../../database/migrations/0007_short_code_checksums.sql"),
+    },
+];
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Could not access the database (Error: {0})")]
+    Postgres(#[from] tokio_postgres::Error),
+    #[error("Migration {version} ({name}) failed (Error: {why})")]
+    Failed {
+        version: i32,
+        name: &'static str,
+        why: String,
+    },
+    #[error("The database is at version {0}, which is newer than the migrations of this version of the bot")]
+    UnknownVersion(i32),
+}
+
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+// What migrate did, or would have done in a dry run
+#[derive(Debug)]
+pub struct MigrationPlan {
+    // The tables were created from database/*.sql before there were migrations
+    pub adopts_baseline: bool,
+    pub pending: Vec<&'static Migration>,
+}
+
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub async fn migrate(client: &mut Client, dry_run: bool) -> Result<MigrationPlan, MigrationError> {
+    client.execute("SELECT pg_advisory_lock($1)", &[&LOCK_ID]).await?;
+    let result = apply(client, dry_run).await;
+    client.execute("SELECT pg_advisory_unlock($1)", &[&LOCK_ID]).await?;
+
+    result
+}
+
+async fn apply(client: &mut Client, dry_run: bool) -> Result<MigrationPlan, MigrationError> {
+    let plan = plan(client).await?;
+
+    if dry_run {
+        return Ok(plan);
+    }
+
+    let transaction = client.transaction().await?;
+    transaction.batch_execute(CREATE_VERSIONS_TABLE).await?;
+
+    if plan.adopts_baseline {
+        let baseline = &MIGRATIONS[0];
+        transaction
+            .execute(
+                "INSERT INTO SchemaMigrations(version, name) VALUES ($1, $2)",
+                &[&baseline.version, &baseline.name],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+
+    for migration in plan.pending.iter() {
+        let failed = |e: tokio_postgres::Error| MigrationError::Failed {
+            version: migration.version,
+            name: migration.name,
+            why: e.to_string(),
+        };
+
+        let transaction = client.transaction().await?;
+        transaction.batch_execute(migration.sql).await.map_err(failed)?;
+        transaction
+            .execute(
+                "INSERT INTO SchemaMigrations(version, name) VALUES ($1, $2)",
+                &[&migration.version, &migration.name],
+            )
+            .await
+            .map_err(failed)?;
+        transaction.commit().await.map_err(failed)?;
+
+        println!("Applied database migration {} ({})", migration.version, migration.name);
+    }
+
+    Ok(plan)
+}
+
+async fn plan(client: &Client) -> Result<MigrationPlan, MigrationError> {
+    let mut applied = HashSet::new();
+    let mut adopts_baseline = false;
+
+    if table_exists(client, "schemamigrations").await? {
+        for row in client.query("SELECT version FROM SchemaMigrations", &[]).await? {
+            applied.insert(row.get::<&str, i32>("version"));
+        }
+    } else {
+        adopts_baseline = table_exists(client, "players").await?;
+    }
+
+    if let Some(version) = applied.iter().copied().find(|v| *v > latest_version()) {
+        return Err(MigrationError::UnknownVersion(version));
+    }
+
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .filter(|m| !(adopts_baseline && m.version == MIGRATIONS[0].version))
+        .collect();
+
+    Ok(MigrationPlan {
+        adopts_baseline,
+        pending,
+    })
+}
+
+// Only looks at the current schema, which is public unless the search path says otherwise
+async fn table_exists(client: &Client, name: &str) -> Result<bool, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = $1) AS found",
+            &[&name],
+        )
+        .await?;
+
+    Ok(row.get("found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::test_database::TestDatabase;
+
+    fn versions(plan: &MigrationPlan) -> Vec<i32> {
+        plan.pending.iter().map(|m| m.version).collect()
+    }
+
+    #[test]
+    fn versions_are_ordered() {
+        assert!(MIGRATIONS.windows(2).all(|m| m[0].version + 1 == m[1].version));
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn migrates_empty_database_once() {
+        let database = TestDatabase::empty().await;
+        let mut client = database.client().await;
+
+        let dry_run = migrate(&mut client, true).await.unwrap();
+        let first = migrate(&mut client, false).await.unwrap();
+        let second = migrate(&mut client, false).await.unwrap();
+        let recorded: i64 = client
+            .query_one("SELECT count(*) FROM SchemaMigrations", &[])
+            .await
+            .unwrap()
+            .get(0);
+        drop(client);
+        database.drop().await;
+
+        assert!(!dry_run.adopts_baseline);
+        assert_eq!(versions(&dry_run), (1..=latest_version()).collect::<Vec<i32>>());
+        assert_eq!(versions(&first), versions(&dry_run));
+        assert!(versions(&second).is_empty());
+        assert_eq!(recorded, latest_version() as i64);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn adopts_baseline_schema_without_losing_data() {
+        let database = TestDatabase::empty().await;
+        let mut client = database.client().await;
+        client.batch_execute(MIGRATIONS[0].sql).await.unwrap();
+        client
+            .batch_execute(
+                "INSERT INTO Players(discordName, minecraftName, registrationCode) VALUES ('1001', 'Steve', 'ABC123');
+                 INSERT INTO AuthenticationRequests(minecraftName, minecraftServer, ipAddress) VALUES ('Steve', 'survival', '127.0.0.1');
+                 UPDATE AuthenticationRequests SET handled = TRUE;",
+            )
+            .await
+            .unwrap();
+
+        let plan = migrate(&mut client, false).await.unwrap();
+        let player = client
+            .query_one("SELECT minecraftName, registrationCode = HashRegistrationCode('ABC123') AS hashed FROM Players", &[])
+            .await
+            .unwrap();
+        let state: String = client
+            .query_one("SELECT state FROM AuthenticationRequests", &[])
+            .await
+            .unwrap()
+            .get("state");
+        drop(client);
+        database.drop().await;
+
+        assert!(plan.adopts_baseline);
+        assert_eq!(versions(&plan), (2..=latest_version()).collect::<Vec<i32>>());
+        assert_eq!(player.get::<&str, String>("minecraftname"), "Steve");
+        assert!(player.get::<&str, bool>("hashed"));
+        assert_eq!(state, "expired");
+    }
+}
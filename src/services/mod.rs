@@ -1,3 +1,7 @@
 pub mod database;
 pub mod embed;
+pub mod localization;
+pub mod migrations;
 pub mod queue;
+pub mod registration_code;
+pub mod settings;
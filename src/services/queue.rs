@@ -1,73 +1,158 @@
 use async_std::task;
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
 use core::time::Duration;
 use futures::channel::mpsc;
 use futures::channel::mpsc::{UnboundedReceiver, Sender};
-use futures::{stream, FutureExt, StreamExt, TryStreamExt};
-use tokio_postgres::{connect, AsyncMessage, Client, Error, NoTls};
+use futures::{stream, SinkExt, Stream, StreamExt};
+use std::sync::Arc;
+use tokio_postgres::{connect, AsyncMessage, Client, NoTls};
+
+use super::database::{AuthenticationState, Database};
+use super::settings::AuthenticationSettings;
 
 pub struct MessageQueue<'a> {
     connection_string: &'a str,
-    tx_bot: Sender<AsyncMessage>
+    database: Database,
+    tx_bot: Sender<i32>,
+    // For the approval timeout of each Minecraft server
+    settings: AuthenticationSettings,
 }
 
 impl MessageQueue<'_> {
     pub async fn start(&mut self) {
-        let (mut client, mut rx) = refresh_connection(&self.connection_string).await;
-        client.batch_execute("LISTEN bot_updates").await.unwrap();
-
         loop {
-            if client.is_closed() {
-                (client, rx) = refresh_connection(&self.connection_string).await;
-                client.batch_execute("LISTEN bot_updates").await.unwrap();
+            // The client has to be kept alive for as long as we are listening
+            let (_client, rx) = refresh_connection(self.connection_string).await;
+
+            // Requests created while we were not listening never produced a notification for us, and the
+            // ones prompted before a restart are no longer waited for
+            if !self.replay_unhandled_requests().await || !forward_notifications(rx, &mut self.tx_bot).await {
+                println!("The bot is no longer receiving authentication requests, stopping the message queue");
+                return;
             }
 
-            match rx.try_next() {
-                Ok(Some(AsyncMessage::Notification(message))) => {
-                    loop {
-                        match self.tx_bot.try_send(AsyncMessage::Notification(message.clone())) {
-                            Ok(()) => break,
-                            Err(e) => {
-                                println!("An error occured when forwarding message to bot: {}", e);
-                                println!("Trying again...");
-                                task::sleep(Duration::from_secs(1)).await;
-                            }
-                        }
-                    }
-                },
-                Ok(_) => (),
-                Err(_) => {
-                    task::sleep(Duration::from_millis(500)).await;
-                }
+            println!("The Postgres connection was unexpectedly closed.");
+        }
+    }
+
+    // Requests that can no longer be answered within the approval timeout of their server are expired,
+    // the others are prompted (again). One that is still waited for is postponed by the bot until it is done.
+    async fn replay_unhandled_requests(&mut self) -> bool {
+        let requests = match self.database.get_unhandled_authentication_requests().await {
+            Ok(requests) => requests,
+            Err(e) => {
+                println!("Could not retrieve unhandled authentication requests: {}", e);
+                return true;
+            }
+        };
+        let mut request_ids = Vec::new();
+
+        for request in requests {
+            let approval_timeout = self.settings.for_server(&request.minecraft_server).approval_timeout;
+
+            if request.state_age < approval_timeout.as_secs() as i64 {
+                request_ids.push(request.id);
+                continue;
+            }
+
+            println!("Expiring authentication request {}. It was {:?} for longer than the approval timeout", request.id, request.state);
+
+            if let Err(e) = self.database.set_authentication_state(&request.id, &AuthenticationState::Expired, None).await {
+                println!("Could not expire authentication request {}: {}", request.id, e);
             }
         }
+
+        if !request_ids.is_empty() {
+            println!("Replaying {} unhandled authentication requests", request_ids.len());
+        }
+
+        for request_id in request_ids {
+            if let Err(e) = self.tx_bot.send(request_id).await {
+                println!("An error occured when forwarding authentication request to bot: {}", e);
+                return false;
+            }
+        }
+
+        true
     }
 
-    pub async fn new(connection_string: &str, tx_bot: Sender<AsyncMessage>) -> Result<MessageQueue, Error> {
-        Ok(MessageQueue { connection_string, tx_bot })
+    pub async fn new(
+        connection_string: &str,
+        db_connection_pool: Arc<Pool<PostgresConnectionManager<NoTls>>>,
+        tx_bot: Sender<i32>,
+        settings: AuthenticationSettings,
+    ) -> Result<MessageQueue<'_>, Box<dyn std::error::Error>> {
+        let database = Database::new(db_connection_pool).await?;
+        Ok(MessageQueue { connection_string, database, tx_bot, settings })
     }
 }
 
-async fn refresh_connection(connection_string: &str) -> (Client, UnboundedReceiver<AsyncMessage>) {
-    println!("The Postgres connection was unexpectedly closed.");
+// Forwards the authentication request ids of incoming notifications to the bot until the stream ends.
+// Returns false if the bot has stopped listening.
+async fn forward_notifications<S>(mut rx: S, tx_bot: &mut Sender<i32>) -> bool
+where
+    S: Stream<Item = AsyncMessage> + Unpin,
+{
+    while let Some(message) = rx.next().await {
+        if let AsyncMessage::Notification(notification) = message {
+            let request_id = match notification.payload().parse::<i32>() {
+                Ok(request_id) => request_id,
+                Err(e) => {
+                    println!("Received invalid authentication request id '{}': {}", notification.payload(), e);
+                    continue;
+                }
+            };
 
+            if let Err(e) = tx_bot.send(request_id).await {
+                println!("An error occured when forwarding authentication request to bot: {}", e);
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+async fn refresh_connection(connection_string: &str) -> (Client, UnboundedReceiver<AsyncMessage>) {
     loop {
-        println!("Trying to re-establish Postgres connection...");
+        println!("Trying to establish Postgres connection...");
         match connect(connection_string, NoTls).await {
             Ok((client, mut connection)) => {
                 let (tx, rx) = mpsc::unbounded();
-                let stream = stream::poll_fn(move |cx| connection.poll_message(cx))
-                    .map_err(|e| panic!("{}", e));
-                let c = stream.forward(tx).map(|r| {
-                    println!("{:?}", &r);
-                    r.unwrap()
+
+                // Drive the connection on the runtime. Once it fails, the sender is dropped which ends the receiving stream.
+                tokio::spawn(async move {
+                    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+
+                    while let Some(message) = messages.next().await {
+                        match message {
+                            Ok(message) => {
+                                if tx.unbounded_send(message).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                println!("An error occured on the Postgres connection: {}", e);
+                                break;
+                            }
+                        }
+                    }
                 });
-                tokio::spawn(c);
-                println!("The Postgres connection has been re-established!");
+
+                if let Err(e) = client.batch_execute("LISTEN bot_updates").await {
+                    println!("An error occured when listening for Postgres notifications: {}", e);
+                    println!("Sleeping for 5 seconds...");
+                    task::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+
+                println!("The Postgres connection has been established!");
                 return (client, rx);
             }
             Err(e) => {
                 println!(
-                    "An error occured when trying to re-establish the postgres connection: {}",
+                    "An error occured when trying to establish the postgres connection: {}",
                     e
                 );
                 println!("Sleeping for 5 seconds...");
@@ -76,3 +161,108 @@ async fn refresh_connection(connection_string: &str) -> (Client, UnboundedReceiv
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::test_database::TestDatabase;
+    use std::collections::HashMap;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::{Context, Poll};
+
+    struct CountingStream<S> {
+        inner: S,
+        polls: Arc<AtomicUsize>,
+    }
+
+    impl<S: Stream + Unpin> Stream for CountingStream<S> {
+        type Item = S::Item;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.polls.fetch_add(1, Ordering::SeqCst);
+            Pin::new(&mut self.inner).poll_next(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_queue_does_not_spin() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let rx = CountingStream {
+            inner: stream::pending::<AsyncMessage>(),
+            polls: Arc::clone(&polls),
+        };
+        let (mut tx_bot, _rx_bot) = mpsc::channel::<i32>(1);
+
+        let forwarder = tokio::spawn(async move { forward_notifications(rx, &mut tx_bot).await });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        assert!(!forwarder.is_finished());
+        assert!(polls.load(Ordering::SeqCst) <= 2);
+        forwarder.abort();
+    }
+
+    #[tokio::test]
+    async fn closed_connection_ends_forwarding() {
+        let (tx, rx) = mpsc::unbounded::<AsyncMessage>();
+        let (mut tx_bot, _rx_bot) = mpsc::channel::<i32>(1);
+        drop(tx);
+
+        assert!(forward_notifications(rx, &mut tx_bot).await);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn replays_unhandled_requests_and_expires_stale_prompts() {
+        let database = TestDatabase::new().await;
+        let db = Database::new(Arc::clone(&database.pool)).await.unwrap();
+        db.add_player("1001", "CODE", &15).await.unwrap();
+        db.set_minecraft_user("1001", "Steve").await.unwrap();
+        let mut request_ids = Vec::new();
+
+        for state in [AuthenticationState::Pending, AuthenticationState::Prompted, AuthenticationState::Prompted] {
+            let request_id = db.add_authentication_request("Steve", "127.0.0.1", "lobby").await.unwrap();
+            db.set_authentication_state(&request_id, &state, None).await.unwrap();
+            request_ids.push(request_id);
+        }
+
+        // The last one was prompted before the bot stopped and can no longer be answered
+        database
+            .client()
+            .await
+            .execute(
+                "UPDATE AuthenticationRequests SET statechanged = statechanged - INTERVAL '1 hour' WHERE id=$1",
+                &[&request_ids[2]],
+            )
+            .await
+            .unwrap();
+
+        let (tx_bot, rx_bot) = mpsc::channel::<i32>(10);
+        let settings = AuthenticationSettings {
+            session_minutes: 30,
+            approval_timeout_seconds: 60,
+            message_deletion_seconds: 60,
+            trusted_network_days: 30,
+            rate_limit_window_seconds: 600,
+            max_prompts_per_player: 3,
+            max_prompts_per_ip: 5,
+            moderator_channel_id: None,
+            max_concurrent: 10,
+            servers: HashMap::new(),
+        };
+        let mut queue = MessageQueue::new("", Arc::clone(&database.pool), tx_bot, settings).await.unwrap();
+
+        assert!(queue.replay_unhandled_requests().await);
+        drop(queue);
+
+        let replayed: Vec<i32> = rx_bot.collect().await;
+        let expired = db.get_recent_authentication_requests("Steve", None, &10).await.unwrap();
+        database.drop().await;
+
+        assert_eq!(replayed, request_ids[..2]);
+        assert_eq!(
+            expired.iter().filter(|r| r.state == AuthenticationState::Expired).count(),
+            1
+        );
+    }
+}
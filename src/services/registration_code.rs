@@ -0,0 +1,314 @@
+use std::future::Future;
+
+use rand::{distributions::Alphanumeric, seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use super::database::DatabaseError;
+
+// Without 0/O, 1/I/L so the code can be read back without guessing. Its length is prime, which
+// lets the checksum catch any single wrong character and any two swapped neighbours in codes
+// shorter than the alphabet (see CodeFormat::max_length).
+static SHORT_CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKMNPQRSTUVWXYZ";
+static CODE_WORDS: &[&str] = &[
+    "acorn", "amber", "anvil", "apple", "arrow", "badge", "basin", "beach", "berry", "birch",
+    "blaze", "bloom", "brick", "brook", "cabin", "camel", "candle", "canoe", "cedar", "chalk",
+    "cliff", "cloud", "clover", "comet", "coral", "crane", "creek", "crown", "daisy", "delta",
+    "dune", "eagle", "ember", "fable", "falcon", "fern", "field", "flame", "flint", "forest",
+    "fox", "frost", "garden", "glade", "grove", "harbor", "hazel", "heron", "hill", "honey",
+    "island", "ivory", "jade", "kettle", "lagoon", "lantern", "lemon", "lily", "lotus", "maple",
+    "marble", "meadow", "melon", "mint", "moss", "nectar", "oak", "ocean", "olive", "orchid",
+    "otter", "owl", "panda", "pearl", "pebble", "pine", "plum", "pond", "poppy", "quartz",
+    "rabbit", "raven", "reef", "ridge", "river", "robin", "sage", "salmon", "sand", "shell",
+    "slate", "snow", "spruce", "star", "stone", "storm", "sun", "swan", "thorn", "tiger",
+    "tulip", "valley", "violet", "walnut", "wave", "willow", "wolf", "wren", "yarrow", "zebra",
+];
+
+// How often a new code is tried when the generated one is already in use
+const MAX_ATTEMPTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeFormat {
+    Short,
+    Words,
+    Long,
+}
+
+impl CodeFormat {
+    // Characters for short and long codes, words for word codes
+    pub fn default_length(&self) -> usize {
+        match self {
+            CodeFormat::Short => 6,
+            CodeFormat::Words => 3,
+            CodeFormat::Long => 32,
+        }
+    }
+
+    // Past this the checksum of short codes stops catching every single wrong character, since a
+    // position would be weighed by a multiple of the alphabet length. Longer word and long codes
+    // would no longer comfortably fit in the Minecraft chat.
+    pub fn max_length(&self) -> usize {
+        match self {
+            CodeFormat::Short => SHORT_CODE_ALPHABET.len() - 1,
+            CodeFormat::Words => 10,
+            CodeFormat::Long => 128,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CodeFormat::Short => "short",
+            CodeFormat::Words => "words",
+            CodeFormat::Long => "long",
+        }
+    }
+}
+
+pub trait CodeGenerator: Send + Sync {
+    fn generate(&self) -> String;
+}
+
+// Random characters from an unambiguous alphabet followed by a checksum character, e.g. 7KX9QMR
+pub struct ShortCodeGenerator {
+    pub length: usize,
+}
+
+impl CodeGenerator for ShortCodeGenerator {
+    fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        let mut code: String = (0..self.length)
+            .map(|_| *SHORT_CODE_ALPHABET.choose(&mut rng).unwrap() as char)
+            .collect();
+        code.push(short_code_checksum(&code).unwrap());
+        code
+    }
+}
+
+// Words joined by dashes, e.g. maple-river-stone
+pub struct WordCodeGenerator {
+    pub words: usize,
+}
+
+impl CodeGenerator for WordCodeGenerator {
+    fn generate(&self) -> String {
+        let mut rng = rand::thread_rng();
+        (0..self.words)
+            .map(|_| *CODE_WORDS.choose(&mut rng).unwrap())
+            .collect::<Vec<&str>>()
+            .join("-")
+    }
+}
+
+// Random mixed-case letters and digits
+pub struct LongCodeGenerator {
+    pub length: usize,
+}
+
+impl CodeGenerator for LongCodeGenerator {
+    fn generate(&self) -> String {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(self.length)
+            .map(char::from)
+            .collect()
+    }
+}
+
+impl Default for LongCodeGenerator {
+    fn default() -> LongCodeGenerator {
+        LongCodeGenerator { length: 32 }
+    }
+}
+
+fn short_code_checksum(code: &str) -> Option<char> {
+    let mut sum = 0;
+
+    for (i, c) in code.chars().enumerate() {
+        let value = SHORT_CODE_ALPHABET.iter().position(|a| *a as char == c)?;
+        sum += (i + 1) * value;
+    }
+
+    Some(SHORT_CODE_ALPHABET[sum % SHORT_CODE_ALPHABET.len()] as char)
+}
+
+// The codes typed in Minecraft are checked by IsValidShortCode in the database, which has to agree with this
+pub fn is_valid_short_code(code: &str) -> bool {
+    match code.char_indices().last() {
+        Some((i, checksum)) if i > 0 => short_code_checksum(&code[..i]) == Some(checksum),
+        _ => false,
+    }
+}
+
+pub fn code_generator(format: CodeFormat, length: usize) -> Box<dyn CodeGenerator> {
+    match format {
+        CodeFormat::Short => Box::new(ShortCodeGenerator { length }),
+        CodeFormat::Words => Box::new(WordCodeGenerator { words: length }),
+        CodeFormat::Long => Box::new(LongCodeGenerator { length }),
+    }
+}
+
+// Stores a newly generated code with `store`, generating another one whenever the code is already taken.
+// Returns the code that was stored.
+pub async fn store_unique_code<F, Fut>(
+    generator: &dyn CodeGenerator,
+    mut store: F,
+) -> Result<String, DatabaseError>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<(), DatabaseError>>,
+{
+    for _ in 0..MAX_ATTEMPTS {
+        let code = generator.generate();
+
+        match store(code.clone()).await {
+            Ok(()) => return Ok(code),
+            Err(DatabaseError::DuplicateRegistrationCode) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(DatabaseError::DuplicateRegistrationCode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::{test_database::TestDatabase, Database};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    // Hands out the given codes in order
+    struct FixedCodeGenerator {
+        codes: Mutex<Vec<&'static str>>,
+    }
+
+    impl FixedCodeGenerator {
+        fn new(codes: &[&'static str]) -> FixedCodeGenerator {
+            let mut codes = codes.to_vec();
+            codes.reverse();
+            FixedCodeGenerator { codes: Mutex::new(codes) }
+        }
+    }
+
+    impl CodeGenerator for FixedCodeGenerator {
+        fn generate(&self) -> String {
+            self.codes.lock().unwrap().pop().unwrap().to_string()
+        }
+    }
+
+    async fn store_in(existing: &Mutex<HashSet<String>>, code: String) -> Result<(), DatabaseError> {
+        if existing.lock().unwrap().insert(code) {
+            Ok(())
+        } else {
+            Err(DatabaseError::DuplicateRegistrationCode)
+        }
+    }
+
+    #[test]
+    fn short_codes_use_unambiguous_alphabet_and_checksum() {
+        let generator = ShortCodeGenerator { length: 6 };
+
+        for _ in 0..100 {
+            let code = generator.generate();
+
+            assert_eq!(code.len(), 7);
+            assert!(!code.contains(['0', 'O', '1', 'I', 'L']));
+            assert!(is_valid_short_code(&code));
+        }
+    }
+
+    #[test]
+    fn checksum_catches_typos() {
+        let code = ShortCodeGenerator { length: 6 }.generate();
+        let chars: Vec<char> = code.chars().collect();
+
+        let replacement = if chars[0] == 'A' { 'B' } else { 'A' };
+        let substituted: String = std::iter::once(replacement).chain(chars[1..].iter().copied()).collect();
+        assert!(!is_valid_short_code(&substituted));
+
+        if chars[0] != chars[1] {
+            let mut swapped = chars.clone();
+            swapped.swap(0, 1);
+            assert!(!is_valid_short_code(&swapped.into_iter().collect::<String>()));
+        }
+
+        assert!(!is_valid_short_code(""));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a Postgres database in POSTGRES_URL"]
+    async fn minecraft_server_is_told_about_mistyped_codes() {
+        let database = TestDatabase::new().await;
+        let code = ShortCodeGenerator { length: 6 }.generate();
+        Database::new(Arc::clone(&database.pool))
+            .await
+            .unwrap()
+            .add_player("1001", &code, &15)
+            .await
+            .unwrap();
+
+        let mut chars: Vec<char> = code.chars().collect();
+        chars[0] = if chars[0] == 'A' { 'B' } else { 'A' };
+        let mistyped: String = chars.into_iter().collect();
+        let client = database.client().await;
+        let mut results = Vec::new();
+
+        for (code, checked) in [(mistyped.as_str(), false), ("maple-river-stone", false), (&code.to_lowercase(), true)] {
+            let row = client
+                .query_one("SELECT RegisterMinecraftPlayer($1, 'Steve'), IsValidShortCode(upper($1))", &[&code])
+                .await
+                .unwrap();
+            assert_eq!(row.get::<usize, bool>(1), checked);
+            results.push(row.get::<usize, String>(0));
+        }
+
+        drop(client);
+        database.drop().await;
+
+        assert_eq!(results, ["mistyped", "invalid", "linked"]);
+    }
+
+    #[test]
+    fn generators_use_configured_lengths() {
+        assert_eq!(code_generator(CodeFormat::Short, 4).generate().len(), 5);
+        assert_eq!(code_generator(CodeFormat::Long, 32).generate().len(), 32);
+        assert_eq!(code_generator(CodeFormat::Words, 3).generate().split('-').count(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_when_code_is_taken() {
+        let existing = Mutex::new(HashSet::from(["AAAA".to_string(), "BBBB".to_string()]));
+        let generator = FixedCodeGenerator::new(&["AAAA", "BBBB", "CCCC"]);
+
+        let code = store_unique_code(&generator, |code| store_in(&existing, code)).await;
+
+        assert_eq!(code.unwrap(), "CCCC");
+        assert!(existing.lock().unwrap().contains("CCCC"));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_repeated_collisions() {
+        let existing = Mutex::new(HashSet::from(["AAAA".to_string()]));
+        let generator = FixedCodeGenerator::new(&["AAAA"; MAX_ATTEMPTS]);
+
+        let code = store_unique_code(&generator, |code| store_in(&existing, code)).await;
+
+        assert!(matches!(code, Err(DatabaseError::DuplicateRegistrationCode)));
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors() {
+        let generator = FixedCodeGenerator::new(&["AAAA", "BBBB"]);
+
+        let code = store_unique_code(&generator, |_| async {
+            Err(DatabaseError::InsertError {
+                data: "player".to_string(),
+                why: "connection closed".to_string(),
+            })
+        })
+        .await;
+
+        assert!(matches!(code, Err(DatabaseError::InsertError { .. })));
+        assert_eq!(generator.generate(), "BBBB");
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+use config::{Config, ConfigError, Environment, File};
+use serde::Deserialize;
+
+use super::registration_code::{code_generator, CodeFormat, CodeGenerator};
+
+static DEFAULT_CONFIG_FILE: &str = "config";
+// Tables of the config file that can be overridden by environment variables such as DISCORD__TOKEN
+static SECTIONS: &[&str] = &["DISCORD", "POSTGRES", "AUTHENTICATION", "REGISTRATION", "LOCALIZATION", "FEATURES"];
+// Environment variables of earlier versions of the bot and the settings they were renamed to
+static LEGACY_VARIABLES: &[(&str, &str)] = &[
+    ("DISCORD_TOKEN", "discord.token"),
+    ("GUILD_ID", "discord.guild_id"),
+    ("POSTGRES_URL", "postgres.url"),
+    ("MAX_CONCURRENT_AUTHENTICATIONS", "authentication.max_concurrent"),
+];
+// Settings without a default, reported by name instead of as a deserialization error
+static REQUIRED: &[&str] = &["postgres.url"];
+// Only required by the commands that talk to Discord, but always together
+static DISCORD_REQUIRED: &[&str] = &["discord.token", "discord.guild_id"];
+// Handled login requests, and with them the history the rate limit counts, are removed after this long
+// by the NotifyBot trigger of the database
+const HANDLED_REQUEST_RETENTION_SECONDS: u32 = 30 * 60;
+
+// Every setting of the bot, read from the file in CONFIG_FILE (config.toml, config.yaml, ... by default)
+// with environment variables overriding the values in the file. See ENV-VARIABLES for all of them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Settings {
+    // Not needed by the commands that only work on the database, see Settings::discord
+    #[serde(default)]
+    pub discord: Option<DiscordSettings>,
+    pub postgres: PostgresSettings,
+    pub authentication: AuthenticationSettings,
+    pub registration: RegistrationSettings,
+    pub localization: LocalizationSettings,
+    pub features: FeatureSettings,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct DiscordSettings {
+    pub token: String,
+    // The guild the slash commands are registered in
+    pub guild_id: u64,
+}
+
+// Keeps the token out of the logs
+impl std::fmt::Debug for DiscordSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiscordSettings")
+            .field("token", &"<hidden>")
+            .field("guild_id", &self.guild_id)
+            .finish()
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct PostgresSettings {
+    // e.g. host=localhost port=5432 user=postgres password=postgres dbname=postgres
+    pub url: String,
+    pub pool_size: u32,
+    // How long to wait for a connection from the pool before a command fails
+    pub connection_timeout_seconds: u64,
+}
+
+// Keeps the password in the connection string out of the logs
+impl std::fmt::Debug for PostgresSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresSettings")
+            .field("url", &"<hidden>")
+            .field("pool_size", &self.pool_size)
+            .field("connection_timeout_seconds", &self.connection_timeout_seconds)
+            .finish()
+    }
+}
+
+// Parts of the bot that can be turned off
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeatureSettings {
+    // The /trusted command and the button that trusts a network when approving a login
+    pub trusted_networks: bool,
+    // The /admin command
+    pub admin_commands: bool,
+}
+
+// A session length stored for the server in the database with the server command takes precedence over session_minutes
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServerAuthenticationOverrides {
+    pub session_minutes: Option<u32>,
+    pub approval_timeout_seconds: Option<u64>,
+    pub message_deletion_seconds: Option<u64>,
+    pub trusted_network_days: Option<u32>,
+}
+
+// Read from the [authentication] table of the config file, e.g.
+//
+// [authentication]
+// session_minutes = 30
+//
+// [authentication.servers.survival]
+// session_minutes = 120
+//
+// Every value can be overridden by an environment variable such as AUTHENTICATION__SESSION_MINUTES
+// or AUTHENTICATION__SERVERS__SURVIVAL__SESSION_MINUTES
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthenticationSettings {
+    pub session_minutes: u32,
+    pub approval_timeout_seconds: u64,
+    pub message_deletion_seconds: u64,
+    pub trusted_network_days: u32,
+    pub rate_limit_window_seconds: u32,
+    pub max_prompts_per_player: u32,
+    pub max_prompts_per_ip: u32,
+    // Guild channel that receives an alert whenever a player locks their account
+    pub moderator_channel_id: Option<u64>,
+    // Login requests that are waiting for an answer at the same time
+    pub max_concurrent: usize,
+    #[serde(default)]
+    pub servers: HashMap<String, ServerAuthenticationOverrides>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAuthenticationSettings {
+    pub session_minutes: u32,
+    pub approval_timeout: Duration,
+    pub message_deletion: Duration,
+    pub trusted_network_days: u32,
+}
+
+// Read from the [registration] table of the config file, or environment variables such as
+// REGISTRATION__CODE_TTL_MINUTES
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegistrationSettings {
+    pub code_ttl_minutes: u32,
+    pub code_format: CodeFormat,
+    // Characters for short and long codes, words for word codes
+    pub code_length: Option<usize>,
+}
+
+impl RegistrationSettings {
+    pub fn code_generator(&self) -> Box<dyn CodeGenerator> {
+        code_generator(
+            self.code_format,
+            self.code_length.unwrap_or_else(|| self.code_format.default_length()),
+        )
+    }
+}
+
+// Read from the [localization] table of the config file, e.g.
+//
+// [localization]
+// default_locale = "sv"
+// templates_directory = "messages"
+//
+// [localization.variables]
+// server_address = "minecraft.example.com"
+// support_contact = "the server admins"
+//
+// or environment variables such as LOCALIZATION__DEFAULT_LOCALE or LOCALIZATION__VARIABLES__SERVER_ADDRESS
+#[derive(Debug, Clone, Deserialize)]
+pub struct LocalizationSettings {
+    // Language of the DMs to users who have not chosen one with /language, e.g. sv or sv-SE
+    pub default_locale: String,
+    // Directory with <language>.toml files that replace some of the bundled messages
+    pub templates_directory: Option<String>,
+    // Available in every message, e.g. {server_address}
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+impl Settings {
+    pub fn load() -> Result<Settings, ConfigError> {
+        let config_file = env::var("CONFIG_FILE").unwrap_or_else(|_| DEFAULT_CONFIG_FILE.to_string());
+        let mut builder = defaults()?.add_source(File::with_name(&config_file).required(false));
+
+        for section in SECTIONS {
+            builder = builder.add_source(
+                Environment::with_prefix(section)
+                    .separator("__")
+                    .keep_prefix(true)
+                    .ignore_empty(true)
+                    .try_parsing(true),
+            );
+        }
+
+        builder = legacy_variables(builder, |name| env::var(name).ok())?;
+
+        Settings::from_config(builder.build()?)
+    }
+
+    fn from_config(config: Config) -> Result<Settings, ConfigError> {
+        let has_discord = config.get::<config::Value>("discord").is_ok();
+        let required = REQUIRED.iter().chain(DISCORD_REQUIRED.iter().filter(|_| has_discord));
+
+        for key in required {
+            if config.get::<config::Value>(key).is_err() {
+                return Err(ConfigError::NotFound(key.to_string()));
+            }
+        }
+
+        let settings = config.try_deserialize::<Settings>()?;
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: &str| Err(ConfigError::Message(message.to_string()));
+
+        if let Some(discord) = &self.discord {
+            if discord.token.trim().is_empty() {
+                return invalid("discord.token must not be empty");
+            }
+
+            if discord.guild_id == 0 {
+                return invalid("discord.guild_id must be the id of a Discord guild");
+            }
+        }
+
+        if let Err(e) = tokio_postgres::Config::from_str(&self.postgres.url) {
+            return Err(ConfigError::Message(format!("postgres.url is not a valid connection string ({})", e)));
+        }
+
+        if self.postgres.pool_size == 0 {
+            return invalid("postgres.pool_size must be positive");
+        }
+
+        if self.postgres.connection_timeout_seconds == 0 {
+            return invalid("postgres.connection_timeout_seconds must be positive");
+        }
+
+        if self.authentication.max_concurrent == 0 {
+            return invalid("authentication.max_concurrent must be positive");
+        }
+
+        if self.authentication.approval_timeout_seconds == 0 {
+            return invalid("authentication.approval_timeout_seconds must be positive");
+        }
+
+        if self.authentication.rate_limit_window_seconds > HANDLED_REQUEST_RETENTION_SECONDS {
+            return Err(ConfigError::Message(format!(
+                "authentication.rate_limit_window_seconds must be at most {}, older login requests are not kept",
+                HANDLED_REQUEST_RETENTION_SECONDS
+            )));
+        }
+
+        if self.registration.code_length == Some(0) {
+            return invalid("registration.code_length must be positive");
+        }
+
+        let code_format = self.registration.code_format;
+
+        if self.registration.code_length.is_some_and(|length| length > code_format.max_length()) {
+            return Err(ConfigError::Message(format!(
+                "registration.code_length must be at most {} when registration.code_format is \"{}\"",
+                code_format.max_length(),
+                code_format.name()
+            )));
+        }
+
+        Ok(())
+    }
+
+    // For the commands that talk to Discord
+    pub fn discord(&self) -> Result<&DiscordSettings, ConfigError> {
+        self.discord
+            .as_ref()
+            .ok_or_else(|| ConfigError::NotFound(DISCORD_REQUIRED[0].to_string()))
+    }
+}
+
+// The variable that replaced a legacy variable, e.g. DISCORD__GUILD_ID for GUILD_ID
+fn variable_name(key: &str) -> String {
+    key.to_uppercase().replace('.', "__")
+}
+
+// Keeps deployments with the variables of earlier versions working. The new variable wins if both are set.
+fn legacy_variables(
+    mut builder: config::builder::ConfigBuilder<config::builder::DefaultState>,
+    variable: impl Fn(&str) -> Option<String>,
+) -> Result<config::builder::ConfigBuilder<config::builder::DefaultState>, ConfigError> {
+    for (legacy, key) in LEGACY_VARIABLES {
+        let value = match variable(legacy) {
+            Some(value) if !value.is_empty() => value,
+            _ => continue,
+        };
+        let name = variable_name(key);
+
+        if variable(&name).is_some_and(|value| !value.is_empty()) {
+            println!("Ignoring the environment variable {}, which has been replaced by {}", legacy, name);
+            continue;
+        }
+
+        println!("The environment variable {} is deprecated, use {} instead", legacy, name);
+        builder = builder.set_override(*key, value)?;
+    }
+
+    Ok(builder)
+}
+
+fn defaults() -> Result<config::builder::ConfigBuilder<config::builder::DefaultState>, ConfigError> {
+    Config::builder()
+        .set_default("postgres.pool_size", 20)?
+        .set_default("postgres.connection_timeout_seconds", 30)?
+        .set_default("authentication.session_minutes", 30)?
+        .set_default("authentication.approval_timeout_seconds", 30)?
+        .set_default("authentication.message_deletion_seconds", 30)?
+        .set_default("authentication.trusted_network_days", 30)?
+        .set_default("authentication.rate_limit_window_seconds", 600)?
+        .set_default("authentication.max_prompts_per_player", 3)?
+        .set_default("authentication.max_prompts_per_ip", 5)?
+        .set_default("authentication.max_concurrent", 10)?
+        .set_default("registration.code_ttl_minutes", 15)?
+        .set_default("registration.code_format", "short")?
+        .set_default("localization.default_locale", "en")?
+        .set_default("features.trusted_networks", true)?
+        .set_default("features.admin_commands", true)
+}
+
+impl AuthenticationSettings {
+    pub fn for_server(&self, minecraft_server: &str) -> ServerAuthenticationSettings {
+        // Server names coming from environment variables are always lower case
+        let overrides = self
+            .servers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(minecraft_server))
+            .map(|(_, overrides)| overrides.clone())
+            .unwrap_or_default();
+
+        ServerAuthenticationSettings {
+            session_minutes: overrides.session_minutes.unwrap_or(self.session_minutes),
+            approval_timeout: Duration::from_secs(
+                overrides
+                    .approval_timeout_seconds
+                    .unwrap_or(self.approval_timeout_seconds),
+            ),
+            message_deletion: Duration::from_secs(
+                overrides
+                    .message_deletion_seconds
+                    .unwrap_or(self.message_deletion_seconds),
+            ),
+            trusted_network_days: overrides
+                .trusted_network_days
+                .unwrap_or(self.trusted_network_days),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::FileFormat;
+
+    fn settings(source: &str) -> Result<Settings, ConfigError> {
+        Settings::from_config(
+            defaults()
+                .unwrap()
+                .add_source(File::from_str(source, FileFormat::Toml))
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let settings = settings(
+            "[discord]\ntoken = \"token\"\nguild_id = 4\n[postgres]\nurl = \"host=localhost user=postgres\"",
+        )
+        .unwrap();
+
+        assert_eq!(settings.postgres.pool_size, 20);
+        assert_eq!(settings.authentication.max_concurrent, 10);
+        assert!(settings.features.trusted_networks);
+        assert!(!format!("{:?}", settings).contains("\"token\""));
+        assert!(!format!("{:?}", settings).contains("localhost"));
+    }
+
+    #[test]
+    fn discord_is_only_required_when_used() {
+        let settings = settings("[postgres]\nurl = \"host=localhost\"").unwrap();
+
+        assert_eq!(
+            settings.discord().unwrap_err().to_string(),
+            "configuration property \"discord.token\" not found"
+        );
+    }
+
+    #[test]
+    fn maps_legacy_variables() {
+        let variables = HashMap::from([
+            ("DISCORD_TOKEN", "legacy"),
+            ("DISCORD__TOKEN", "token"),
+            ("GUILD_ID", "4"),
+            ("POSTGRES_URL", "host=localhost"),
+        ]);
+        let builder = defaults()
+            .unwrap()
+            .add_source(File::from_str("[discord]\ntoken = \"token\"", FileFormat::Toml));
+        let builder = legacy_variables(builder, |name| variables.get(name).map(|v| v.to_string())).unwrap();
+        let settings = Settings::from_config(builder.build().unwrap()).unwrap();
+
+        assert_eq!(settings.discord().unwrap().token, "token");
+        assert_eq!(settings.discord().unwrap().guild_id, 4);
+        assert_eq!(settings.postgres.url, "host=localhost");
+    }
+
+    #[test]
+    fn rejects_missing_and_invalid_settings() {
+        for (source, expected) in [
+            (
+                "[discord]\ntoken = \"token\"\n[postgres]\nurl = \"host=localhost\"",
+                "configuration property \"discord.guild_id\" not found",
+            ),
+            (
+                "[discord]\ntoken = \"token\"\nguild_id = 4\n[postgres]\nurl = \"host=localhost\"\npool_size = 0",
+                "postgres.pool_size must be positive",
+            ),
+            (
+                "[discord]\ntoken = \"token\"\nguild_id = 4\n[postgres]\nurl = \"host=localhost port=x\"",
+                "postgres.url is not a valid connection string (invalid connection string: invalid value for option `port`)",
+            ),
+            (
+                "[postgres]\nurl = \"host=localhost\"\n[authentication]\nrate_limit_window_seconds = 3600",
+                "authentication.rate_limit_window_seconds must be at most 1800, older login requests are not kept",
+            ),
+            (
+                "[postgres]\nurl = \"host=localhost\"\n[registration]\ncode_length = 31",
+                "registration.code_length must be at most 30 when registration.code_format is \"short\"",
+            ),
+            (
+                "[postgres]\nurl = \"host=localhost\"\n[registration]\ncode_format = \"words\"\ncode_length = 11",
+                "registration.code_length must be at most 10 when registration.code_format is \"words\"",
+            ),
+        ] {
+            assert_eq!(settings(source).unwrap_err().to_string(), expected);
+        }
+    }
+}